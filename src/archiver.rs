@@ -9,6 +9,7 @@ pub enum ArchiverState {
     FailureBackoff,
     Downloading,
     Uploading,
+    CircuitOpen,
 }
 
 impl std::fmt::Display for ArchiverState {
@@ -23,8 +24,12 @@ pub static ARCHIVER_STATES: &[ArchiverState] = &[
     ArchiverState::FailureBackoff,
     ArchiverState::Downloading,
     ArchiverState::Uploading,
+    ArchiverState::CircuitOpen,
 ];
 
+/// How often a stream that is late to start is checked again, in seconds.
+const LIVE_START_POLL_SECONDS: i64 = 60;
+
 pub struct ArchiveBot {
     task_queue: Box<dyn util::TaskQueue>,
     video_downloader: Box<dyn util::VideoDownloader>,
@@ -33,6 +38,13 @@ pub struct ArchiveBot {
     archive_site: Box<dyn util::ArchiveSite>,
     events: Option<tokio::sync::mpsc::UnboundedSender<ArchiverState>>,
     skip_requeue: String,
+    breaker: util::breaker::CircuitBreaker,
+    media_validator: Option<Box<dyn util::MediaValidator>>,
+    probe_policy: util::probe::ProbePolicy,
+    live_max_wait: Option<Duration>,
+    schedule: Option<util::schedule::Schedule>,
+    upcoming_delay: Duration,
+    max_attempts: Option<u32>,
 }
 
 impl ArchiveBot {
@@ -53,9 +65,61 @@ impl ArchiveBot {
             archive_site,
             events,
             skip_requeue,
+            breaker: util::breaker::CircuitBreaker::default(),
+            media_validator: None,
+            probe_policy: util::probe::ProbePolicy::default(),
+            live_max_wait: None,
+            schedule: None,
+            upcoming_delay: Duration::from_secs(60 * 60),
+            max_attempts: None,
         }
     }
 
+    /// Use the given circuit breaker instead of the default one.
+    pub fn with_circuit_breaker(mut self, breaker: util::breaker::CircuitBreaker) -> Self {
+        self.breaker = breaker;
+        self
+    }
+
+    /// Validate downloaded media before uploading it.
+    pub fn with_media_validator(mut self, validator: Box<dyn util::MediaValidator>) -> Self {
+        self.media_validator = Some(validator);
+        self
+    }
+
+    /// Limit which videos get downloaded, based on the pre-flight probe.
+    pub fn with_probe_policy(mut self, policy: util::probe::ProbePolicy) -> Self {
+        self.probe_policy = policy;
+        self
+    }
+
+    /// Record live streams instead of deferring them. Upcoming streams are
+    /// deferred until their scheduled start, and checked again until they are
+    /// `max_wait` late.
+    pub fn with_live_recording(mut self, max_wait: Duration) -> Self {
+        self.live_max_wait = Some(max_wait);
+        self
+    }
+
+    /// Defer tasks that are not due yet, keeping them in `schedule` until they
+    /// are. Upcoming streams are deferred until they are scheduled to start,
+    /// and streams that are live or still being processed by `upcoming_delay`.
+    pub fn with_schedule(
+        mut self,
+        schedule: util::schedule::Schedule,
+        upcoming_delay: Duration,
+    ) -> Self {
+        self.schedule = Some(schedule);
+        self.upcoming_delay = upcoming_delay;
+        self
+    }
+
+    /// Stop requeuing a task after it failed `max_attempts` times.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
     fn send_event(&self, state: ArchiverState) {
         if let Some(events) = &self.events {
             let _ = events.send(state);
@@ -97,6 +161,19 @@ impl ArchiveBot {
     pub async fn run_one(&self) -> anyhow::Result<()> {
         self.send_event(ArchiverState::Starting);
 
+        // Update tools while nothing is running
+        if let Err(e) = self.video_downloader.maintain().await {
+            warn!("Video downloader maintenance failed: {:#}", e);
+        }
+
+        // Hold off while YouTube is blocking us
+        if self.breaker.is_open() {
+            self.send_event(ArchiverState::CircuitOpen);
+        }
+        self.breaker.wait().await;
+
+        self.release_due_tasks().await;
+
         // Get a task from the queue
         info!("Getting next task from queue");
         let task = self
@@ -106,30 +183,122 @@ impl ArchiveBot {
             .context("Could not get next task from queue")?;
 
         info!("Got task: {:?}", task);
-        let video_id = task.data;
-        match self.run_video(&video_id).await {
+        let task: util::task::Task = match task.data.parse() {
+            Ok(parsed) => parsed,
             Err(e) => {
-                if !self.skip_requeue.is_empty() {
-                    info!("Requeuing {}", video_id);
-                    let _ = self.task_queue.insert(video_id).await;
-                }
-                return Err(e);
+                // The task is gone from the queue, so this is the only trace
+                error!("Dropping malformed task {:?}: {:#}", task.data, e);
+                util::metrics::counter_inc("archivebot_tasks_dead_lettered_total", &[]);
+                return Err(e.context("Could not parse task"));
             }
-            x => {
-                return x;
+        };
+        // Tasks that cannot be deferred go back into the queue below
+        let res = match task.not_before.filter(|_| !task.is_due(Self::now())) {
+            Some(not_before) => self.defer(&task, not_before).await,
+            None => match task.kind {
+                util::task::TaskKind::Video => self.run_video(&task).await,
+                util::task::TaskKind::LiveChat => self.run_live_chat(&task).await,
+                util::task::TaskKind::Comments => self.run_comments(&task).await,
+                util::task::TaskKind::Playlist => self.run_playlist(&task).await,
+                util::task::TaskKind::Refresh => self.run_refresh(&task).await,
+            },
+        };
+        match res {
+            Err(e) => {
+                let failure = e.downcast_ref::<util::DownloadFailure>().copied();
+                // Whichever stage failed, a bot check or rate limit trips the breaker
+                if let Some(failure) = failure {
+                    self.breaker.record(Some(failure));
+                    util::metrics::counter_inc(
+                        "archivebot_download_failures_total",
+                        &[("reason", failure.as_str())],
+                    );
+                }
+                let permanent = failure.is_some_and(|f| f.is_permanent());
+                // Waiting for a video to come out is not a failed attempt
+                let retry = util::task::Task {
+                    attempt: match failure {
+                        Some(util::DownloadFailure::Upcoming) => task.attempt,
+                        _ => task.attempt + 1,
+                    },
+                    ..task.clone()
+                };
+                if permanent {
+                    info!("Not requeuing {}, the failure is permanent", task);
+                } else if self.max_attempts.is_some_and(|max| retry.attempt >= max) {
+                    info!("Not requeuing {}, it failed {} times", task, retry.attempt);
+                    util::metrics::counter_inc("archivebot_tasks_abandoned_total", &[]);
+                } else if !self.skip_requeue.is_empty() {
+                    info!("Requeuing {}", retry);
+                    let _ = self.task_queue.insert(retry.to_string()).await;
+                }
+                Err(e)
             }
+            x => x,
         }
     }
 
-    pub async fn run_video(&self, video_id: &str) -> anyhow::Result<()> {
-        let video_url = format!("https://www.youtube.com/watch?v={}", video_id);
+    pub async fn run_video(&self, task: &util::task::Task) -> anyhow::Result<()> {
+        let source = util::source::resolve(task)?;
+        let video_id = source.archive_id(task);
+        let video_id = video_id.as_str();
+        let video_url = source.url(task)?;
+        if let Some(requester) = &task.requester {
+            info!("Archiving {} for {}", video_id, requester);
+        }
 
         // Ensure the video doesn't already exist in the archive
-        if self.archive_site.is_archived(video_id).await? {
+        if task.force {
+            info!("Archiving video again, as forced by the task");
+        } else if self.archive_site.is_archived(video_id).await? {
             info!("Video already archived, skipping");
             return Ok(());
         }
 
+        // Pick the proxy and profile once, for all downloads of the video
+        let mut options = self
+            .video_downloader
+            .prepare(&video_url, &Self::download_options(task))
+            .await
+            .context("Could not prepare download")?;
+
+        // Look at the video before writing any bytes
+        let probe = self.preflight(&video_url, &options).await?;
+
+        // Record streams live once they start, or come back after they end
+        let delay = self.upcoming_delay.as_secs() as i64;
+        match (probe.availability, self.live_max_wait) {
+            (util::probe::Availability::Upcoming, max_wait) => {
+                let start = self
+                    .scheduled_start(source, task, &probe)
+                    .await
+                    .ok_or_else(|| {
+                        anyhow::Error::new(util::DownloadFailure::Upcoming)
+                            .context("Stream has no scheduled start time")
+                    })?
+                    .timestamp();
+                let now = Self::now();
+                let not_before = match max_wait {
+                    _ if start > now => start,
+                    // Streams often start late, so keep checking for a while
+                    Some(max_wait) if now - start < max_wait.as_secs() as i64 => {
+                        now + LIVE_START_POLL_SECONDS
+                    }
+                    _ => now + delay,
+                };
+                return self.defer(task, not_before).await;
+            }
+            (util::probe::Availability::Live, None)
+            | (util::probe::Availability::Processing, _) => {
+                return self.defer(task, Self::now() + delay).await;
+            }
+            _ => {}
+        }
+        options.live = probe.availability == util::probe::Availability::Live;
+        if options.live {
+            info!("Recording stream {} live", video_id);
+        }
+
         // Create a temporary directory
         let destination = util::tempdir()
             .await
@@ -139,36 +308,99 @@ impl ArchiveBot {
             destination.path().to_str().unwrap_or("???")
         );
 
-        // Download the video
+        // Download the video and its live chat. The live chat is given up on
+        // if the video fails.
         info!("Downloading video {}", video_url);
         self.send_event(ArchiverState::Downloading);
-        let dl_res = self
+        let video = self
             .video_downloader
-            .download(&video_url, destination.path())
-            .await
-            .context("Could not download video")?;
+            .download(&video_url, destination.path(), &options);
+        let live_chat =
+            self.video_downloader
+                .download_live_chat(&video_url, destination.path(), &options);
+        tokio::pin!(video, live_chat);
+        let mut live_chat_res = None;
+        let dl_res = loop {
+            tokio::select! {
+                res = &mut video => break res,
+                res = &mut live_chat, if live_chat_res.is_none() => live_chat_res = Some(res),
+            }
+        }
+        .context("Could not download video")?;
 
-        if !dl_res.output.status.success() {
-            return Err(anyhow::anyhow!(
+        let failure = if !dl_res.output.status.success() || dl_res.failure.is_some() {
+            Some(dl_res.failure.unwrap_or(util::DownloadFailure::Unknown))
+        } else {
+            None
+        };
+        if let Some(failure) = failure {
+            return Err(anyhow::Error::new(failure).context(format!(
                 "Could not download video: downloader exited with code {}, stderr: {}",
                 dl_res.output.status.code().unwrap_or(-1),
                 String::from_utf8_lossy(&dl_res.output.stderr)
-            ));
+            )));
+        }
+        self.breaker.record(None);
+
+        let live_chat = match live_chat_res {
+            Some(res) => res,
+            None => live_chat.await,
         }
+        .unwrap_or_else(|e| {
+            warn!("Could not download live chat: {:#}", e);
+            util::LiveChatStatus::Failed
+        });
+        let live_chat_stats = Self::process_live_chat(destination.path(), live_chat).await;
 
         // Extract metadata
         info!("Extracting metadata");
-        let metadata = self
+        let mut metadata = self
             .metadata_extractor
             .extract(destination.path())
             .await
             .context("Could not extract metadata")?;
+        metadata.live_chat = Some(live_chat);
+        metadata.live_chat_stats = live_chat_stats;
+        let storage_path = Self::storage_path(task, video_id, None);
+        metadata.storage_path = (storage_path != video_id).then_some(storage_path.clone());
+        metadata.requested_by = task.requester.clone();
+        // Follow-up tasks of generic videos have only the archived ID
+        if source.name() == "generic" {
+            metadata.source_url = Some(video_url.clone());
+        }
+
+        // Make sure the media is not truncated or corrupt
+        if let Some(validator) = &self.media_validator {
+            info!("Validating media");
+            if let Err(e) = validator.validate(destination.path(), &metadata).await {
+                util::metrics::counter_inc(
+                    "archivebot_download_failures_total",
+                    &[("reason", util::DownloadFailure::InvalidMedia.as_str())],
+                );
+                return Err(e.context("Downloaded media is invalid"));
+            }
+        }
+
+        // Comments may fail without failing the video
+        info!("Downloading comments");
+        metadata.comments = self
+            .video_downloader
+            .download_comments(&video_url, destination.path(), &options, None)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Could not download comments: {:#}", e);
+                Some(util::comments::CommentsInfo::new(
+                    util::comments::CommentsStatus::Failed,
+                ))
+            });
+        source.map_metadata(task, &mut metadata);
+        Self::record_files(destination.path(), &mut metadata)?;
 
         // Upload the video
         info!("Uploading video");
         self.send_event(ArchiverState::Uploading);
         self.uploader
-            .upload(destination.path(), video_id)
+            .upload(destination.path(), &storage_path)
             .await
             .context("Could not upload video")?;
 
@@ -179,9 +411,512 @@ impl ArchiveBot {
             .await
             .context("Could not add video to archive")?;
 
+        // Try the live chat again later
+        if live_chat == util::LiveChatStatus::Failed {
+            info!("Queueing live chat task for {}", video_id);
+            let follow_up = util::task::Task {
+                kind: util::task::TaskKind::LiveChat,
+                attempt: 0,
+                force: false,
+                ..task.clone()
+            };
+            if let Err(e) = self.task_queue.insert(follow_up.to_string()).await {
+                warn!("Could not queue live chat task: {:#}", e);
+            }
+        }
+
+        self.send_event(ArchiverState::Idle);
+        Ok(())
+    }
+
+    /// Add the live chat to a video that was archived without it.
+    pub async fn run_live_chat(&self, task: &util::task::Task) -> anyhow::Result<()> {
+        let source = util::source::resolve(task)?;
+        let video_id = source.archive_id(task);
+        let video_id = video_id.as_str();
+
+        let mut metadata = self
+            .archive_site
+            .get(video_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Video {} is not archived", video_id))?;
+        let video_url = Self::archived_url(source, task, &metadata)?;
+        if metadata.live_chat == Some(util::LiveChatStatus::Present) {
+            info!("Live chat already archived, skipping");
+            return Ok(());
+        }
+
+        let destination = util::tempdir()
+            .await
+            .context("Could not create temporary directory")?;
+
+        info!("Downloading live chat of {}", video_url);
+        self.send_event(ArchiverState::Downloading);
+        let live_chat = self
+            .video_downloader
+            .download_live_chat(
+                &video_url,
+                destination.path(),
+                &Self::download_options(task),
+            )
+            .await
+            .context("Could not download live chat")?;
+        if live_chat == util::LiveChatStatus::Failed {
+            anyhow::bail!("Could not download live chat of {}", video_id);
+        }
+
+        if live_chat == util::LiveChatStatus::Present {
+            metadata.live_chat_stats = Self::process_live_chat(destination.path(), live_chat).await;
+
+            info!("Uploading live chat");
+            self.send_event(ArchiverState::Uploading);
+            self.uploader
+                .upload(
+                    destination.path(),
+                    &Self::storage_path(task, video_id, Some(&metadata)),
+                )
+                .await
+                .context("Could not upload live chat")?;
+            Self::record_files(destination.path(), &mut metadata)?;
+        }
+
+        info!("Updating live chat status in archive");
+        metadata.live_chat = Some(live_chat);
+        self.archive_site
+            .archive(video_id, &metadata)
+            .await
+            .context("Could not update video in archive")?;
+
+        self.send_event(ArchiverState::Idle);
+        Ok(())
+    }
+
+    /// Fetch the comments of an archived video that are not archived yet.
+    pub async fn run_comments(&self, task: &util::task::Task) -> anyhow::Result<()> {
+        let source = util::source::resolve(task)?;
+        let video_id = source.archive_id(task);
+        let video_id = video_id.as_str();
+
+        let mut metadata = self
+            .archive_site
+            .get(video_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Video {} is not archived", video_id))?;
+        let video_url = Self::archived_url(source, task, &metadata)?;
+
+        let destination = util::tempdir()
+            .await
+            .context("Could not create temporary directory")?;
+
+        info!("Downloading comments of {}", video_url);
+        self.send_event(ArchiverState::Downloading);
+        let comments = match self
+            .video_downloader
+            .download_comments(
+                &video_url,
+                destination.path(),
+                &Self::download_options(task),
+                metadata.comments.as_ref(),
+            )
+            .await
+            .context("Could not download comments")?
+        {
+            Some(comments) => comments,
+            None => {
+                info!("Comments are disabled for this video, skipping");
+                return Ok(());
+            }
+        };
+        // Keep what a timed out fetch got, later fetches continue from it
+        if comments.status != util::comments::CommentsStatus::Present && comments.count == 0 {
+            anyhow::bail!("Could not download comments of {}", video_id);
+        }
+
+        if comments.count > 0 {
+            info!("Uploading comments");
+            self.send_event(ArchiverState::Uploading);
+            self.uploader
+                .upload(
+                    destination.path(),
+                    &Self::storage_path(task, video_id, Some(&metadata)),
+                )
+                .await
+                .context("Could not upload comments")?;
+            Self::record_files(destination.path(), &mut metadata)?;
+        }
+
+        info!("Updating comments in archive");
+        match &mut metadata.comments {
+            Some(existing) => existing.merge(comments),
+            None => metadata.comments = Some(comments),
+        }
+        self.archive_site
+            .archive(video_id, &metadata)
+            .await
+            .context("Could not update video in archive")?;
+
+        self.send_event(ArchiverState::Idle);
+        Ok(())
+    }
+
+    /// Update the metadata of an archived video from its current info.json,
+    /// without touching its media.
+    pub async fn run_refresh(&self, task: &util::task::Task) -> anyhow::Result<()> {
+        let source = util::source::resolve(task)?;
+        let video_id = source.archive_id(task);
+        let video_id = video_id.as_str();
+
+        let mut metadata = self
+            .archive_site
+            .get(video_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Video {} is not archived", video_id))?;
+        let video_url = Self::archived_url(source, task, &metadata)?;
+
+        let destination = util::tempdir()
+            .await
+            .context("Could not create temporary directory")?;
+
+        info!("Downloading metadata of {}", video_url);
+        self.send_event(ArchiverState::Downloading);
+        self.video_downloader
+            .download_info(
+                &video_url,
+                destination.path(),
+                &Self::download_options(task),
+            )
+            .await
+            .context("Could not download metadata")?;
+        let mut fresh = self
+            .metadata_extractor
+            .extract(destination.path())
+            .await
+            .context("Could not extract metadata")?;
+        source.map_metadata(task, &mut fresh);
+
+        let changed = metadata.refresh(fresh);
+        if changed.is_empty() {
+            info!("Metadata of {} is unchanged", video_id);
+            util::metrics::counter_inc(
+                "archivebot_metadata_refreshes_total",
+                &[("result", "unchanged")],
+            );
+        } else {
+            info!("Updating {} of {} in archive", changed.join(", "), video_id);
+            self.archive_site
+                .archive(video_id, &metadata)
+                .await
+                .context("Could not update video in archive")?;
+            util::metrics::counter_inc(
+                "archivebot_metadata_refreshes_total",
+                &[("result", "updated")],
+            );
+        }
+
+        self.send_event(ArchiverState::Idle);
+        Ok(())
+    }
+
+    /// Queue the videos of a channel or playlist that pass the task's filter
+    /// and are not archived yet. Nested playlists, such as the tabs of a
+    /// channel, are queued as playlist tasks of their own.
+    pub async fn run_playlist(&self, task: &util::task::Task) -> anyhow::Result<()> {
+        let source = util::source::resolve(task)?;
+        let url = source.playlist_url(task)?;
+        let filter = task.filter.clone().unwrap_or_default();
+
+        info!("Listing {}", url);
+        self.send_event(ArchiverState::Downloading);
+        let entries = self
+            .video_downloader
+            .list_entries(&url, &Self::download_options(task))
+            .await
+            .context("Could not list playlist")?;
+        info!("Found {} entries", entries.len());
+
+        // A run that failed halfway is retried, so skip the entries it already queued
+        let pending: std::collections::HashSet<_> = match self.task_queue.list().await {
+            Ok(list) => list
+                .tasks
+                .iter()
+                .filter_map(|data| data.parse::<util::task::Task>().ok())
+                .map(|t| (t.id, t.kind))
+                .collect(),
+            Err(e) => {
+                warn!(
+                    "Could not list queued tasks, entries may be queued twice: {:#}",
+                    e
+                );
+                Default::default()
+            }
+        };
+
+        let (mut queued, mut pending_count, mut archived, mut filtered) = (0, 0, 0, 0);
+        for entry in entries {
+            let child = if entry.is_playlist {
+                util::task::Task {
+                    id: entry.id,
+                    kind: util::task::TaskKind::Playlist,
+                    url: entry.url,
+                    platform: task.platform.clone(),
+                    not_before: None,
+                    storage_path: None,
+                    attempt: 0,
+                    force: false,
+                    ..task.clone()
+                }
+            } else {
+                if !filter.matches(&entry) {
+                    filtered += 1;
+                    continue;
+                }
+                util::task::Task {
+                    // Only generic videos need their URL to be downloaded
+                    url: entry.url.filter(|_| source.name() == "generic"),
+                    platform: (source.name() != "youtube").then(|| source.name().into()),
+                    profile: task.profile.clone(),
+                    cookies: task.cookies.clone(),
+                    priority: task.priority,
+                    requester: task.requester.clone(),
+                    ..util::task::Task::new(&entry.id)
+                }
+            };
+            if pending.contains(&(child.id.clone(), child.kind)) {
+                pending_count += 1;
+                continue;
+            }
+            if !child.force
+                && child.kind == util::task::TaskKind::Video
+                && self
+                    .archive_site
+                    .is_archived(&source.archive_id(&child))
+                    .await?
+            {
+                archived += 1;
+                continue;
+            }
+            self.task_queue
+                .insert(child.to_string())
+                .await
+                .context("Could not queue playlist entry")?;
+            queued += 1;
+        }
+
+        info!(
+            "Queued {} entries, skipped {} already queued, {} archived and {} filtered entries",
+            queued, pending_count, archived, filtered
+        );
+        for (result, count) in [
+            ("queued", queued),
+            ("already_queued", pending_count),
+            ("archived", archived),
+            ("filtered", filtered),
+        ] {
+            util::metrics::counter_add(
+                "archivebot_playlist_entries_total",
+                &[("result", result)],
+                count as f64,
+            );
+        }
+        self.send_event(ArchiverState::Idle);
+        Ok(())
+    }
+
+    /// Probe a video and check it against the probe policy. Fails the way a
+    /// download would if the video cannot or should not be downloaded.
+    async fn preflight(
+        &self,
+        video_url: &str,
+        options: &util::DownloadOptions,
+    ) -> anyhow::Result<util::probe::VideoProbe> {
+        info!("Probing video {}", video_url);
+        let probe = self.video_downloader.probe(video_url, options).await?;
+        info!(
+            "Video is {}, duration {:?}s, estimated size {:?} bytes",
+            probe.availability.as_str(),
+            probe.duration,
+            probe.filesize
+        );
+
+        let free_disk = if self.probe_policy.checks_disk() {
+            util::probe::free_disk_space(&util::get_cache_dir().await?)?
+        } else {
+            0
+        };
+        if let Err(violation) = self.probe_policy.check(&probe, free_disk) {
+            util::metrics::counter_inc(
+                "archivebot_probe_rejections_total",
+                &[("reason", violation.as_str())],
+            );
+            return Err(match violation {
+                util::probe::PolicyViolation::TooLarge(reason) => {
+                    anyhow::Error::new(util::DownloadFailure::Rejected).context(reason)
+                }
+                util::probe::PolicyViolation::DiskFull(reason) => {
+                    anyhow::anyhow!("Not enough disk space: {}", reason)
+                }
+            });
+        }
+
+        Ok(probe)
+    }
+
+    /// When an upcoming stream is scheduled to start, if known. Only YouTube
+    /// streams are looked up through the metadata extractor.
+    async fn scheduled_start(
+        &self,
+        source: &dyn util::source::Source,
+        task: &util::task::Task,
+        probe: &util::probe::VideoProbe,
+    ) -> Option<chrono::DateTime<chrono::Utc>> {
+        let timestamps = match source.name() {
+            "youtube" => {
+                self.metadata_extractor
+                    .timestamps(source.video_id(task))
+                    .await
+            }
+            _ => Ok(util::MetadataTimestamps::default()),
+        };
+        match timestamps {
+            Ok(timestamps) => timestamps
+                .scheduled_start_time
+                .and_then(|t| chrono::DateTime::parse_from_rfc3339(&t).ok())
+                .map(|t| t.with_timezone(&chrono::Utc)),
+            Err(e) => {
+                warn!("Could not get scheduled start time: {:#}", e);
+                None
+            }
+        }
+        .or_else(|| {
+            probe
+                .release_timestamp
+                .and_then(|t| chrono::DateTime::from_timestamp(t, 0))
+        })
+    }
+
+    /// Put a task back to run no earlier than `not_before`, in seconds since
+    /// the epoch. Without a schedule, or if the schedule cannot take the task,
+    /// it fails as not available yet and is requeued by `run_one`.
+    async fn defer(&self, task: &util::task::Task, not_before: i64) -> anyhow::Result<()> {
+        let task = util::task::Task {
+            not_before: Some(not_before),
+            ..task.clone()
+        };
+        util::metrics::counter_inc("archivebot_tasks_deferred_total", &[]);
+        let res = match &self.schedule {
+            Some(schedule) => {
+                info!("Deferring {} in the local schedule", task);
+                schedule.add(task)
+            }
+            None => Err(anyhow::anyhow!("No schedule to defer tasks in")),
+        };
+        if let Err(e) = res {
+            return Err(
+                anyhow::Error::new(util::DownloadFailure::Upcoming).context(format!(
+                    "Video is not available until {}: {:#}",
+                    not_before, e
+                )),
+            );
+        }
         self.send_event(ArchiverState::Idle);
         Ok(())
     }
+
+    /// Move tasks from the local schedule back into the task queue once due.
+    async fn release_due_tasks(&self) {
+        let schedule = match &self.schedule {
+            Some(schedule) => schedule,
+            None => return,
+        };
+        let due = match schedule.take_due(Self::now()) {
+            Ok(due) => due,
+            Err(e) => {
+                warn!("Could not read due tasks from schedule: {:#}", e);
+                return;
+            }
+        };
+        for task in due {
+            info!("Releasing scheduled task {}", task);
+            if let Err(e) = self.task_queue.insert(task.to_string()).await {
+                warn!("Could not release scheduled task {}: {:#}", task, e);
+                let _ = schedule.add(task);
+            }
+        }
+    }
+
+    fn now() -> i64 {
+        chrono::Utc::now().timestamp()
+    }
+
+    /// Add the files in `workdir` to the file list of the metadata, replacing
+    /// entries of the same name.
+    fn record_files(
+        workdir: &std::path::Path,
+        metadata: &mut util::Metadata,
+    ) -> anyhow::Result<()> {
+        for entry in std::fs::read_dir(workdir).context("Could not read workdir")? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            metadata.files.retain(|f| f.name != name);
+            metadata.files.push(util::MetadataFileEntry {
+                name,
+                size: entry.metadata()?.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Turn the downloaded live chat into compact artifacts. The raw chat is
+    /// kept if that fails.
+    async fn process_live_chat(
+        workdir: &std::path::Path,
+        status: util::LiveChatStatus,
+    ) -> Option<util::livechat::LiveChatStats> {
+        if status != util::LiveChatStatus::Present {
+            return None;
+        }
+        util::livechat::process(workdir).await.unwrap_or_else(|e| {
+            warn!("Could not process live chat: {:#}", e);
+            None
+        })
+    }
+
+    /// Directory a video is stored in, by default its archive ID with the
+    /// namespace separator and slashes replaced. The task can override where
+    /// an archived video was first stored.
+    fn storage_path(
+        task: &util::task::Task,
+        video_id: &str,
+        metadata: Option<&util::Metadata>,
+    ) -> String {
+        task.storage_path
+            .as_deref()
+            .or(metadata.and_then(|m| m.storage_path.as_deref()))
+            .map(String::from)
+            .unwrap_or_else(|| video_id.replace([':', '/'], "_"))
+    }
+
+    /// URL to download an archived video from again. Generic videos fall back
+    /// to the URL they were archived from.
+    fn archived_url(
+        source: &dyn util::source::Source,
+        task: &util::task::Task,
+        metadata: &util::Metadata,
+    ) -> anyhow::Result<String> {
+        source.url(&util::task::Task {
+            url: task.url.clone().or_else(|| metadata.source_url.clone()),
+            ..task.clone()
+        })
+    }
+
+    fn download_options(task: &util::task::Task) -> util::DownloadOptions {
+        util::DownloadOptions {
+            profile: task.profile.clone(),
+            cookies: task.cookies.clone(),
+            live: false,
+            session: None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -210,6 +945,40 @@ mod test {
         }
     }
 
+    // Mock a queue that keeps what is inserted, failing on one task ID
+    #[derive(Clone, Default)]
+    struct RecordingTasq {
+        tasks: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+        fail_on: std::sync::Arc<std::sync::Mutex<Option<String>>>,
+    }
+    #[async_trait]
+    impl util::TaskQueue for RecordingTasq {
+        async fn insert(&self, data: String) -> anyhow::Result<util::TaskInsertResponse> {
+            let task: util::task::Task = data.parse()?;
+            if self.fail_on.lock().unwrap().as_ref() == Some(&task.id) {
+                anyhow::bail!("Queue unavailable");
+            }
+            self.tasks.lock().unwrap().push(data);
+            Ok(util::TaskInsertResponse { key: task.id })
+        }
+
+        async fn list(&self) -> anyhow::Result<util::TaskListResponse> {
+            let tasks = self.tasks.lock().unwrap().clone();
+            Ok(util::TaskListResponse {
+                count: tasks.len(),
+                tasks,
+            })
+        }
+
+        async fn consume(&self) -> anyhow::Result<util::TaskConsumeResponse> {
+            let data = self.tasks.lock().unwrap().remove(0);
+            Ok(util::TaskConsumeResponse {
+                key: "test".into(),
+                data,
+            })
+        }
+    }
+
     // Mock the Youtube-dl client
     struct MockYTDL;
     #[async_trait]
@@ -218,6 +987,7 @@ mod test {
             &self,
             url: &str,
             destination: &Path,
+            _options: &util::DownloadOptions,
         ) -> anyhow::Result<util::VideoDownloadResult> {
             assert_eq!(
                 url, "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
@@ -231,7 +1001,55 @@ mod test {
                 .output()
                 .await
                 .unwrap();
-            Ok(util::VideoDownloadResult { output })
+            Ok(util::VideoDownloadResult {
+                output,
+                failure: None,
+            })
+        }
+
+        async fn list_entries(
+            &self,
+            _url: &str,
+            _options: &util::DownloadOptions,
+        ) -> anyhow::Result<Vec<util::playlist::PlaylistEntry>> {
+            Ok(vec![
+                util::playlist::PlaylistEntry {
+                    id: "UCuAXFkgsw1L7xaCfnd5JJOw-streams".into(),
+                    url: Some("https://www.youtube.com/@RickAstleyYT/streams".into()),
+                    is_playlist: true,
+                    content: util::playlist::ContentType::Video,
+                    date: None,
+                },
+                util::playlist::PlaylistEntry {
+                    id: "dQw4w9WgXcQ".into(),
+                    url: None,
+                    is_playlist: false,
+                    content: util::playlist::ContentType::Video,
+                    date: None,
+                },
+            ])
+        }
+    }
+
+    // Mock a downloader that YouTube is blocking
+    struct BlockedYTDL;
+    #[async_trait]
+    impl util::VideoDownloader for BlockedYTDL {
+        async fn download(
+            &self,
+            _url: &str,
+            _destination: &Path,
+            _options: &util::DownloadOptions,
+        ) -> anyhow::Result<util::VideoDownloadResult> {
+            unimplemented!()
+        }
+
+        async fn list_entries(
+            &self,
+            _url: &str,
+            _options: &util::DownloadOptions,
+        ) -> anyhow::Result<Vec<util::playlist::PlaylistEntry>> {
+            Err(anyhow::Error::new(util::DownloadFailure::BotCheck).context("Sign in"))
         }
     }
 
@@ -259,7 +1077,7 @@ mod test {
                 files: vec![],
                 drive_base: "blah".into(),
                 archived_timestamp: chrono::Utc::now().to_rfc3339(),
-                timestamps: None,
+                ..Default::default()
             })
         }
     }
@@ -282,6 +1100,10 @@ mod test {
             Ok(false)
         }
 
+        async fn get(&self, _video_id: &str) -> anyhow::Result<Option<util::Metadata>> {
+            unimplemented!()
+        }
+
         async fn archive(&self, video_id: &str, _metadata: &util::Metadata) -> anyhow::Result<()> {
             assert_eq!(video_id, "dQw4w9WgXcQ", "Unexpected video ID");
             Ok(())
@@ -313,4 +1135,139 @@ mod test {
         let event = rx.try_recv();
         assert!(event.is_err());
     }
+
+    #[tokio::test]
+    async fn test_defer() {
+        let dir = tempfile::tempdir().unwrap();
+        let bot = ArchiveBot::new(
+            Box::new(MockTasq),
+            Box::new(MockYTDL),
+            Box::new(MockMetadataExtractor),
+            Box::new(MockRclone),
+            Box::new(MockArchiveSite),
+            None,
+            "".into(),
+        );
+        let task = util::task::Task::new("dQw4w9WgXcQ");
+        let not_before = ArchiveBot::now() + 3600;
+        assert!(bot.defer(&task, not_before).await.is_err());
+
+        let bot = bot.with_schedule(
+            util::schedule::Schedule::load(dir.path().join("schedule.json")).unwrap(),
+            Duration::from_secs(60),
+        );
+        bot.defer(&task, not_before).await.unwrap();
+        bot.release_due_tasks().await;
+        let schedule = bot.schedule.as_ref().unwrap();
+        assert_eq!(schedule.len(), 1);
+        assert_eq!(
+            schedule.take_due(not_before).unwrap()[0].not_before,
+            Some(not_before)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_playlist() {
+        let queue = RecordingTasq::default();
+        *queue.fail_on.lock().unwrap() = Some("dQw4w9WgXcQ".into());
+        let bot = ArchiveBot::new(
+            Box::new(queue.clone()),
+            Box::new(MockYTDL),
+            Box::new(MockMetadataExtractor),
+            Box::new(MockRclone),
+            Box::new(MockArchiveSite),
+            None,
+            "".into(),
+        );
+        let task: util::task::Task = r#"{"id":"UCuAXFkgsw1L7xaCfnd5JJOw","kind":"playlist"}"#
+            .parse()
+            .unwrap();
+        assert!(bot.run_playlist(&task).await.is_err());
+        assert_eq!(queue.tasks.lock().unwrap().len(), 1);
+
+        // The retry only queues what the failed run did not
+        *queue.fail_on.lock().unwrap() = None;
+        bot.run_playlist(&task).await.unwrap();
+        let ids: Vec<_> = queue
+            .tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|data| data.parse::<util::task::Task>().unwrap().id)
+            .collect();
+        assert_eq!(ids, ["UCuAXFkgsw1L7xaCfnd5JJOw-streams", "dQw4w9WgXcQ"]);
+    }
+
+    #[tokio::test]
+    async fn test_defer_without_schedule() {
+        let queue = RecordingTasq::default();
+        let bot = ArchiveBot::new(
+            Box::new(queue.clone()),
+            Box::new(MockYTDL),
+            Box::new(MockMetadataExtractor),
+            Box::new(MockRclone),
+            Box::new(MockArchiveSite),
+            None,
+            "true".into(),
+        )
+        .with_max_attempts(1);
+        let task = util::task::Task {
+            not_before: Some(ArchiveBot::now() + 3600),
+            ..util::task::Task::new("dQw4w9WgXcQ")
+        };
+        queue.tasks.lock().unwrap().push(task.to_string());
+
+        // The task goes back into the queue as it was, without using up an attempt
+        assert!(bot.run_one().await.is_err());
+        let requeued: util::task::Task = queue.tasks.lock().unwrap()[0].parse().unwrap();
+        assert_eq!(requeued.not_before, task.not_before);
+        assert_eq!(requeued.attempt, 0);
+    }
+
+    #[tokio::test]
+    async fn test_breaker_records_any_stage() {
+        let queue = RecordingTasq::default();
+        let bot = ArchiveBot::new(
+            Box::new(queue.clone()),
+            Box::new(BlockedYTDL),
+            Box::new(MockMetadataExtractor),
+            Box::new(MockRclone),
+            Box::new(MockArchiveSite),
+            None,
+            "".into(),
+        );
+        queue
+            .tasks
+            .lock()
+            .unwrap()
+            .push(r#"{"id":"UCuAXFkgsw1L7xaCfnd5JJOw","kind":"playlist"}"#.into());
+
+        assert!(bot.run_one().await.is_err());
+        assert!(bot.breaker.is_open());
+    }
+
+    #[test]
+    fn test_generic_follow_up() {
+        let task: util::task::Task =
+            r#"{"id":"example.com:clips/1","kind":"refresh"}"#.parse().unwrap();
+        let source = util::source::resolve(&task).unwrap();
+        let video_id = source.archive_id(&task);
+        assert_eq!(
+            ArchiveBot::storage_path(&task, &video_id, None),
+            "example.com_clips_1"
+        );
+
+        // Without a URL in the task or the archive there is nothing to download
+        let mut metadata = util::Metadata::default();
+        let err = ArchiveBot::archived_url(source, &task, &metadata).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<util::DownloadFailure>(),
+            Some(&util::DownloadFailure::Rejected)
+        );
+        metadata.source_url = Some("https://example.com/clips/1".into());
+        assert_eq!(
+            ArchiveBot::archived_url(source, &task, &metadata).unwrap(),
+            "https://example.com/clips/1"
+        );
+    }
 }
@@ -1,12 +1,16 @@
 use anyhow::Context;
 
-// Macro to generate a config struct from a list of fields.
+// Macro to generate a config struct from a list of fields. Fields listed
+// after the semicolon are optional and fall back to the given default.
 macro_rules! envcfg {
-    ($($name:ident),*) => {
+    ($($name:ident),* ; $($opt_name:ident = $default:expr),* $(,)?) => {
         pub struct Config {
             $(
                 pub $name: String,
             )*
+            $(
+                pub $opt_name: String,
+            )*
         }
 
         impl Config {
@@ -16,6 +20,10 @@ macro_rules! envcfg {
                         $name: std::env::var(stringify!($name).to_string().to_uppercase())
                             .with_context(|| format!("Missing environment variable {}", stringify!($name).to_string().to_uppercase()))?,
                     )*
+                    $(
+                        $opt_name: std::env::var(stringify!($opt_name).to_string().to_uppercase())
+                            .unwrap_or_else(|_| $default.to_string()),
+                    )*
                 })
             }
         }
@@ -33,5 +41,44 @@ envcfg!(
     youtube_api_key,
     restart_interval_seconds,
     skip_requeue,
-    pot_server_url
+    pot_server_url;
+    youtube_api_daily_quota = "10000",
+    youtube_api_quota_file = "",
+    ytdl_update_interval_seconds = "86400",
+    ytdl_update_failure_threshold = "3",
+    ytdl_update_repo = "yt-dlp/yt-dlp",
+    ytdl_smoke_test_url = "",
+    tool_artifact_source = "",
+    ytdlp_binary = "managed",
+    ytdlp_min_version = "",
+    ffmpeg_binary = "managed",
+    ffmpeg_min_version = "",
+    rclone_binary = "managed",
+    rclone_min_version = "",
+    ytdl_profiles = "",
+    ytdl_default_profile = "default",
+    ytdl_channel_profiles = "",
+    circuit_breaker_cooldown_seconds = "900",
+    circuit_breaker_max_cooldown_seconds = "14400",
+    ytdl_proxies = "",
+    ytdl_proxy_quarantine_seconds = "3600",
+    ytdl_cookie_jars = "",
+    ytdl_channel_cookie_jars = "",
+    pot_health_check_interval_seconds = "60",
+    ffprobe_binary = "managed",
+    ffprobe_min_version = "",
+    media_duration_tolerance_seconds = "5",
+    ytdl_live_chat_retries = "3",
+    max_video_size_bytes = "",
+    max_video_duration_seconds = "",
+    min_free_disk_bytes = "",
+    live_recording_max_wait_seconds = "",
+    schedule_file = "",
+    upcoming_task_delay_seconds = "3600",
+    max_task_attempts = "",
+    watch_channels = "",
+    watch_interval_seconds = "900",
+    watch_seen_file = "",
+    availability_check_interval_seconds = "",
+    availability_sample_size = "50",
 );
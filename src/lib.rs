@@ -31,49 +31,118 @@ pub async fn run() -> anyhow::Result<()> {
     debug!("Loading config");
     let cfg = config::Config::from_env().context("Could not load config")?;
 
-    let ragtag: Box<dyn util::ArchiveSite> = if cfg.archive_base_url.is_empty() {
-        warn!("No archive base URL specified, using mock archive site");
-        Box::new(util::archive::MockRagtag::new().await?)
-    } else {
-        let client = reqwest::Client::builder()
-            .default_headers({
-                let mut headers = reqwest::header::HeaderMap::new();
-                headers.insert(
-                    reqwest::header::AUTHORIZATION,
-                    reqwest::header::HeaderValue::from_str(&cfg.archive_api_authorization)
-                        .context("Could not parse archive API authorization header")?,
-                );
-                headers
-            })
-            .build()
-            .context("Could not create HTTP client")?;
-
-        Box::new(
-            util::archive::Ragtag::new(
-                url::Url::parse(&cfg.archive_base_url)
-                    .context("Could not parse archive base URL")?,
-                Some(client),
-            )
-            .await?,
-        )
+    let ragtag = archive_site(&cfg.archive_base_url, &cfg.archive_api_authorization).await?;
+
+    let update_policy = util::ytdl::UpdatePolicy {
+        check_interval: match cfg
+            .ytdl_update_interval_seconds
+            .parse()
+            .context("Could not parse yt-dlp update interval seconds")?
+        {
+            0 => None,
+            secs => Some(std::time::Duration::from_secs(secs)),
+        },
+        failure_threshold: cfg
+            .ytdl_update_failure_threshold
+            .parse()
+            .context("Could not parse yt-dlp update failure threshold")?,
+        release_repo: cfg.ytdl_update_repo,
+        smoke_test_url: cfg.ytdl_smoke_test_url,
     };
 
+    let artifacts = util::artifacts::ArtifactSource::parse(&cfg.tool_artifact_source)
+        .context("Could not parse tool artifact source")?;
+
+    let profiles = util::profile::DownloadProfiles::parse(
+        &cfg.ytdl_profiles,
+        &cfg.ytdl_default_profile,
+        &cfg.ytdl_channel_profiles,
+    )
+    .context("Could not load download profiles")?;
+
+    let proxies = util::proxy::ProxyPool::parse(
+        &cfg.ytdl_proxies,
+        std::time::Duration::from_secs(
+            cfg.ytdl_proxy_quarantine_seconds
+                .parse()
+                .context("Could not parse proxy quarantine seconds")?,
+        ),
+    )
+    .context("Could not parse proxies")?;
+
+    let pot = util::pot::PotProviders::parse(
+        &cfg.pot_server_url,
+        std::time::Duration::from_secs(
+            cfg.pot_health_check_interval_seconds
+                .parse()
+                .context("Could not parse POT_HEALTH_CHECK_INTERVAL_SECONDS")?,
+        ),
+    )
+    .context("Could not parse POT server URLs")?;
+
+    let api_keys = std::sync::Arc::new(
+        util::apikeys::ApiKeyPool::parse(
+            &cfg.youtube_api_key,
+            cfg.youtube_api_daily_quota
+                .parse()
+                .context("Could not parse YouTube API daily quota")?,
+        )
+        .with_usage_file(match cfg.youtube_api_quota_file.as_str() {
+            "" => util::get_cache_dir().await?.join("youtube_api_quota.json"),
+            path => path.into(),
+        })
+        .context("Could not load YouTube API key usage")?,
+    );
+
     // Instantiate modules
-    let (tasq, ytdlp, meta, rclone) = tokio::join!(
-        util::tasq::Tasq::new(cfg.tasq_url, None),
-        util::ytdl::YTDL::new(cfg.pot_server_url),
-        util::metadata::YTMetadataExtractor::new(cfg.youtube_api_key, None, cfg.drive_base),
+    let (tasq, ytdlp, meta, rclone, ffprobe) = tokio::join!(
+        util::tasq::Tasq::new(cfg.tasq_url.clone(), None),
+        util::ytdl::YTDL::new(
+            pot,
+            update_policy,
+            artifacts.clone(),
+            util::tools::Tool::parse("yt-dlp", &cfg.ytdlp_binary, &cfg.ytdlp_min_version),
+            util::tools::Tool::parse("ffmpeg", &cfg.ffmpeg_binary, &cfg.ffmpeg_min_version),
+            profiles,
+        ),
+        util::metadata::YTMetadataExtractor::new(api_keys.clone(), None, cfg.drive_base.clone(),),
         util::rclone::Rclone::new(
             cfg.rclone_config_data,
             cfg.rclone_remote_name,
-            cfg.rclone_base_directory
+            cfg.rclone_base_directory,
+            artifacts.clone(),
+            util::tools::Tool::parse("rclone", &cfg.rclone_binary, &cfg.rclone_min_version),
+        ),
+        util::ffprobe::FFProbe::new(
+            util::tools::Tool::parse("ffprobe", &cfg.ffprobe_binary, &cfg.ffprobe_min_version),
+            artifacts,
+            cfg.media_duration_tolerance_seconds
+                .parse()
+                .context("Could not parse media duration tolerance seconds")?,
         ),
     );
 
     let tasq = Box::new(tasq.context("Could not create Tasq client")?);
-    let ytdlp = Box::new(ytdlp.context("Could not create YTDL client")?);
+    let ytdlp = Box::new(
+        ytdlp
+            .context("Could not create YTDL client")?
+            .with_proxies(proxies)
+            .with_cookie_jars(
+                util::cookies::CookieJars::parse(
+                    &cfg.ytdl_cookie_jars,
+                    &cfg.ytdl_channel_cookie_jars,
+                )
+                .context("Could not load cookie jars")?,
+            )
+            .with_live_chat_retries(
+                cfg.ytdl_live_chat_retries
+                    .parse()
+                    .context("Could not parse live chat retries")?,
+            ),
+    );
     let meta = Box::new(meta.context("Could not create metadata extractor")?);
     let rclone = Box::new(rclone.context("Could not create Rclone client")?);
+    let ffprobe = Box::new(ffprobe.context("Could not create ffprobe client")?);
 
     // Channel for events
     let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
@@ -85,7 +154,93 @@ pub async fn run() -> anyhow::Result<()> {
         ragtag,
         Some(tx),
         cfg.skip_requeue,
+    )
+    .with_circuit_breaker(util::breaker::CircuitBreaker::new(
+        std::time::Duration::from_secs(
+            cfg.circuit_breaker_cooldown_seconds
+                .parse()
+                .context("Could not parse circuit breaker cooldown seconds")?,
+        ),
+        std::time::Duration::from_secs(
+            cfg.circuit_breaker_max_cooldown_seconds
+                .parse()
+                .context("Could not parse circuit breaker max cooldown seconds")?,
+        ),
+    ))
+    .with_media_validator(ffprobe)
+    .with_probe_policy(
+        util::probe::ProbePolicy::parse(
+            &cfg.max_video_size_bytes,
+            &cfg.max_video_duration_seconds,
+            &cfg.min_free_disk_bytes,
+        )
+        .context("Could not parse probe policy")?,
+    );
+    let schedule_file = match cfg.schedule_file.as_str() {
+        "" => util::get_cache_dir().await?.join("schedule.json"),
+        path => path.into(),
+    };
+    let bot = bot.with_schedule(
+        util::schedule::Schedule::load(schedule_file).context("Could not load schedule")?,
+        std::time::Duration::from_secs(
+            cfg.upcoming_task_delay_seconds
+                .parse()
+                .context("Could not parse upcoming task delay seconds")?,
+        ),
     );
+    let bot = match cfg.max_task_attempts.as_str() {
+        "" => bot,
+        max => bot.with_max_attempts(max.parse().context("Could not parse max task attempts")?),
+    };
+    let bot = match cfg.live_recording_max_wait_seconds.as_str() {
+        "" => bot,
+        secs => bot.with_live_recording(std::time::Duration::from_secs(
+            secs.parse()
+                .context("Could not parse live recording max wait seconds")?,
+        )),
+    };
+    let watcher = match cfg.watch_channels.as_str() {
+        "" => None,
+        channels => Some(
+            util::watcher::Watcher::new(
+                channels,
+                std::time::Duration::from_secs(
+                    cfg.watch_interval_seconds
+                        .parse()
+                        .context("Could not parse watch interval seconds")?,
+                ),
+                Box::new(
+                    util::tasq::Tasq::new(cfg.tasq_url, None)
+                        .await
+                        .context("Could not create Tasq client for watcher")?,
+                ),
+                match cfg.watch_seen_file.as_str() {
+                    "" => util::get_cache_dir().await?.join("watcher_seen.json"),
+                    path => path.into(),
+                },
+                None,
+            )
+            .context("Could not create watcher")?,
+        ),
+    };
+    let monitor = match cfg.availability_check_interval_seconds.as_str() {
+        "" => None,
+        secs => Some(util::availability::AvailabilityMonitor::new(
+            archive_site(&cfg.archive_base_url, &cfg.archive_api_authorization).await?,
+            Box::new(
+                util::metadata::YTMetadataExtractor::new(api_keys, None, cfg.drive_base)
+                    .await
+                    .context("Could not create metadata extractor for availability monitor")?,
+            ),
+            cfg.availability_sample_size
+                .parse()
+                .context("Could not parse availability sample size")?,
+            std::time::Duration::from_secs(
+                secs.parse()
+                    .context("Could not parse availability check interval seconds")?,
+            ),
+        )),
+    };
     let metrics_addr = std::net::SocketAddr::from(([127, 0, 0, 1], 3383));
 
     let exit_after = chrono::Duration::seconds(
@@ -100,6 +255,20 @@ pub async fn run() -> anyhow::Result<()> {
             => info!("Loop exited!"),
         _ = util::metrics::serve_metrics_endpoint(metrics_addr, rx)
             => unreachable!(),
+        _ = async {
+            match &watcher {
+                Some(watcher) => watcher.run_forever().await,
+                None => std::future::pending().await,
+            }
+        }
+            => unreachable!(),
+        _ = async {
+            match &monitor {
+                Some(monitor) => monitor.run_forever().await,
+                None => std::future::pending().await,
+            }
+        }
+            => unreachable!(),
         _ = tokio::signal::ctrl_c()
             => info!("Signal received, shutting down"),
     };
@@ -107,3 +276,35 @@ pub async fn run() -> anyhow::Result<()> {
     info!("Bye!");
     Ok(())
 }
+
+/// Create the archive site client, or a mock if no archive is configured.
+async fn archive_site(
+    base_url: &str,
+    authorization: &str,
+) -> anyhow::Result<Box<dyn util::ArchiveSite>> {
+    if base_url.is_empty() {
+        warn!("No archive base URL specified, using mock archive site");
+        return Ok(Box::new(util::archive::MockRagtag::new().await?));
+    }
+
+    let client = reqwest::Client::builder()
+        .default_headers({
+            let mut headers = reqwest::header::HeaderMap::new();
+            headers.insert(
+                reqwest::header::AUTHORIZATION,
+                reqwest::header::HeaderValue::from_str(authorization)
+                    .context("Could not parse archive API authorization header")?,
+            );
+            headers
+        })
+        .build()
+        .context("Could not create HTTP client")?;
+
+    Ok(Box::new(
+        util::archive::Ragtag::new(
+            url::Url::parse(base_url).context("Could not parse archive base URL")?,
+            Some(client),
+        )
+        .await?,
+    ))
+}
@@ -1,5 +1,4 @@
 #![forbid(unsafe_code)]
-use archivebot;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -0,0 +1,255 @@
+use super::metrics;
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Units of quota a `videos.list` request costs, however many videos and
+/// parts it asks for.
+pub const VIDEOS_LIST_COST: u64 = 1;
+
+/// Label a key in metrics, logs and the usage file. Keys are secret, so the
+/// label is a short hash of the key, which stays the same when keys are added,
+/// removed or reordered.
+fn label(key: &str) -> String {
+    let hash = Sha256::digest(key)
+        .iter()
+        .take(4)
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    format!("key-{}", hash)
+}
+
+/// The day YouTube counts quota against. Quotas reset at midnight Pacific Time.
+fn quota_day(now: DateTime<Utc>) -> NaiveDate {
+    now.with_timezone(&chrono_tz::America::Los_Angeles)
+        .date_naive()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Usage {
+    day: NaiveDate,
+    units: u64,
+    /// The API reported the quota as exceeded before we counted it used up
+    exhausted: bool,
+}
+
+struct Entry {
+    key: String,
+    label: String,
+    usage: Mutex<Usage>,
+}
+
+/// A pool of YouTube Data API keys. Requests are spread across the keys, and
+/// each key's quota is counted until the daily reset.
+pub struct ApiKeyPool {
+    entries: Vec<Entry>,
+    next: AtomicUsize,
+    daily_quota: u64,
+    /// Where usage is kept across restarts, by key label
+    usage_path: Option<PathBuf>,
+}
+
+impl ApiKeyPool {
+    /// Create a pool from a comma-separated list of keys, each with
+    /// `daily_quota` units a day.
+    pub fn parse(keys: &str, daily_quota: u64) -> Self {
+        let day = quota_day(Utc::now());
+        let pool = Self {
+            entries: keys
+                .split(',')
+                .map(str::trim)
+                .filter(|k| !k.is_empty())
+                .map(|key| Entry {
+                    key: key.into(),
+                    label: label(key),
+                    usage: Mutex::new(Usage {
+                        day,
+                        units: 0,
+                        exhausted: false,
+                    }),
+                })
+                .collect(),
+            next: AtomicUsize::new(0),
+            daily_quota,
+            usage_path: None,
+        };
+        for entry in &pool.entries {
+            pool.report_remaining(entry, daily_quota);
+        }
+        pool
+    }
+
+    /// Keep the usage of each key in `path`, so it survives restarts. Usage
+    /// already in the file is loaded.
+    pub fn with_usage_file(mut self, path: PathBuf) -> anyhow::Result<Self> {
+        let saved: BTreeMap<String, Usage> = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).context("Could not parse API key usage")?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e).context("Could not read API key usage"),
+        };
+        for entry in &self.entries {
+            if let Some(usage) = saved.get(&entry.label) {
+                let remaining = match usage.exhausted {
+                    true => 0,
+                    false => self.daily_quota.saturating_sub(usage.units),
+                };
+                *entry.usage.lock().unwrap() = usage.clone();
+                self.report_remaining(entry, remaining);
+            }
+        }
+        self.usage_path = Some(path);
+        Ok(self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn save(&self) {
+        let path = match &self.usage_path {
+            Some(path) => path,
+            None => return,
+        };
+        let usage: BTreeMap<_, _> = self
+            .entries
+            .iter()
+            .map(|e| (e.label.clone(), e.usage.lock().unwrap().clone()))
+            .collect();
+        let tmp = path.with_extension("tmp");
+        let res = serde_json::to_vec(&usage)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(std::fs::write(&tmp, data)?))
+            .and_then(|_| Ok(std::fs::rename(&tmp, path)?));
+        if let Err(e) = res {
+            warn!("Could not save API key usage: {:#}", e);
+        }
+    }
+
+    fn report_remaining(&self, entry: &Entry, remaining: u64) {
+        metrics::gauge_set(
+            "archivebot_youtube_api_quota_remaining",
+            &[("key", &entry.label)],
+            remaining as f64,
+        );
+    }
+
+    /// Pick the next key with `cost` units left today and charge them to it.
+    /// Errors if every key is out of quota.
+    pub fn acquire(&self, cost: u64) -> anyhow::Result<String> {
+        self.acquire_at(cost, Utc::now())
+    }
+
+    fn acquire_at(&self, cost: u64, now: DateTime<Utc>) -> anyhow::Result<String> {
+        if self.entries.is_empty() {
+            anyhow::bail!("No YouTube API key configured");
+        }
+
+        let day = quota_day(now);
+        let start = self.next.fetch_add(1, Ordering::SeqCst);
+        for i in 0..self.entries.len() {
+            let entry = &self.entries[(start + i) % self.entries.len()];
+            {
+                let mut usage = entry.usage.lock().unwrap();
+                if usage.day != day {
+                    *usage = Usage {
+                        day,
+                        units: 0,
+                        exhausted: false,
+                    };
+                }
+                if usage.exhausted || usage.units + cost > self.daily_quota {
+                    continue;
+                }
+                usage.units += cost;
+                self.report_remaining(entry, self.daily_quota - usage.units);
+            }
+            metrics::counter_add(
+                "archivebot_youtube_api_quota_used_total",
+                &[("key", &entry.label)],
+                cost as f64,
+            );
+            self.save();
+            return Ok(entry.key.clone());
+        }
+
+        anyhow::bail!("All YouTube API keys are out of quota until the daily reset")
+    }
+
+    /// Take a key out of the pool until the daily reset, after the API said
+    /// its quota was exceeded.
+    pub fn report_exhausted(&self, key: &str) {
+        if let Some(entry) = self.entries.iter().find(|e| e.key == key) {
+            warn!(
+                "YouTube API {} is out of quota, rotating to the next key",
+                entry.label
+            );
+            entry.usage.lock().unwrap().exhausted = true;
+            metrics::counter_inc(
+                "archivebot_youtube_api_key_rotations_total",
+                &[("key", &entry.label)],
+            );
+            self.report_remaining(entry, 0);
+            self.save();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn test_pool() {
+        assert!(ApiKeyPool::parse("", 100).acquire(1).is_err());
+
+        let pool = ApiKeyPool::parse("a, b", 2);
+        let now = at("2024-01-01T12:00:00Z");
+        assert_eq!(pool.acquire_at(1, now).unwrap(), "a");
+        assert_eq!(pool.acquire_at(1, now).unwrap(), "b");
+
+        // Exhausted keys are skipped until the quota resets
+        pool.report_exhausted("a");
+        assert_eq!(pool.acquire_at(1, now).unwrap(), "b");
+        assert!(pool.acquire_at(1, now).is_err());
+
+        // Still the same day in Pacific Time
+        assert!(pool.acquire_at(1, at("2024-01-02T07:59:59Z")).is_err());
+        assert_eq!(pool.acquire_at(1, at("2024-01-02T08:00:00Z")).unwrap(), "b");
+        assert_eq!(pool.acquire_at(2, at("2024-01-02T08:00:00Z")).unwrap(), "a");
+    }
+
+    #[test]
+    fn test_label() {
+        assert_eq!(label("a"), "key-ca978112");
+        assert_ne!(label("a"), label("b"));
+    }
+
+    #[test]
+    fn test_usage_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("usage.json");
+        let now = Utc::now();
+
+        let pool = ApiKeyPool::parse("a, b", 2)
+            .with_usage_file(path.clone())
+            .unwrap();
+        assert_eq!(pool.acquire_at(2, now).unwrap(), "a");
+        pool.report_exhausted("b");
+
+        // Both keys are still used up after a restart, whatever their order
+        let pool = ApiKeyPool::parse("b, c, a", 2)
+            .with_usage_file(path)
+            .unwrap();
+        assert_eq!(pool.acquire_at(2, now).unwrap(), "c");
+        assert!(pool.acquire_at(1, now).is_err());
+    }
+}
@@ -10,9 +10,20 @@ pub struct Ragtag {
 
 impl Ragtag {
     pub async fn new(url: url::Url, client: Option<reqwest::Client>) -> anyhow::Result<Self> {
-        let client = client.unwrap_or_else(|| reqwest::Client::new());
+        let client = client.unwrap_or_default();
         Ok(Self { url, client })
     }
+
+    /// Search URL for a video. Namespaced IDs can hold characters such as `&`
+    /// or `#`, so the ID is encoded.
+    fn search_url(&self, id: &str) -> anyhow::Result<url::Url> {
+        let mut url = self
+            .url
+            .join("api/v1/search")
+            .context("Could not construct search URL")?;
+        url.query_pairs_mut().append_pair("v", id);
+        Ok(url)
+    }
 }
 
 #[derive(Deserialize)]
@@ -23,6 +34,14 @@ struct SearchResult {
 #[derive(Deserialize)]
 struct Hits {
     total: Total,
+    #[serde(default)]
+    hits: Vec<Hit>,
+}
+
+#[derive(Deserialize)]
+struct Hit {
+    #[serde(rename = "_source")]
+    source: Metadata,
 }
 
 #[derive(Deserialize)]
@@ -34,11 +53,7 @@ struct Total {
 impl ArchiveSite for Ragtag {
     async fn is_archived(&self, id: &str) -> anyhow::Result<bool> {
         self.client
-            .get(
-                self.url
-                    .join(&format!("api/v1/search?v={}", id))
-                    .context("Could not construct search URL")?,
-            )
+            .get(self.search_url(id)?)
             .send()
             .await
             .context("Could not send search request")?
@@ -50,18 +65,44 @@ impl ArchiveSite for Ragtag {
             .context("Could not parse search result")
     }
 
+    async fn get(&self, id: &str) -> anyhow::Result<Option<Metadata>> {
+        let result = self
+            .client
+            .get(self.search_url(id)?)
+            .send()
+            .await
+            .context("Could not send search request")?
+            .error_for_status()
+            .context("Got unexpected status code")?
+            .json::<SearchResult>()
+            .await
+            .context("Could not parse search result")?;
+
+        Ok(result
+            .hits
+            .hits
+            .into_iter()
+            .map(|hit| hit.source)
+            .find(|metadata| metadata.video_id == id))
+    }
+
     async fn archive(&self, id: &str, metadata: &Metadata) -> anyhow::Result<()> {
         let request_body =
             serde_json::to_string(metadata).context("Could not serialize metadata")?;
         debug!("Request body: {}", request_body);
 
+        let mut url = self
+            .url
+            .join("api/v2/archive/")
+            .context("Could not construct archive URL")?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("Could not construct archive URL"))?
+            .pop_if_empty()
+            .push(id);
+
         let res = self
             .client
-            .put(
-                self.url
-                    .join(&format!("api/v2/archive/{}", id))
-                    .context("Could not construct archive URL")?,
-            )
+            .put(url)
             .body(request_body)
             .send()
             .await
@@ -77,6 +118,39 @@ impl ArchiveSite for Ragtag {
 
         Ok(())
     }
+
+    /// Pick random archived videos. Elasticsearch scores the whole archive
+    /// randomly instead of paging deep into it, which it only allows up to
+    /// its result window.
+    async fn sample(&self, count: usize) -> anyhow::Result<Vec<Metadata>> {
+        let query = serde_json::json!({
+            "size": count,
+            "query": {
+                "function_score": {
+                    "query": { "match_all": {} },
+                    "random_score": { "seed": rand::random::<u32>(), "field": "_seq_no" },
+                    "boost_mode": "replace",
+                },
+            },
+        });
+        let result = self
+            .client
+            .post(
+                self.url
+                    .join("api/v1/search")
+                    .context("Could not construct search URL")?,
+            )
+            .json(&query)
+            .send()
+            .await
+            .context("Could not send search request")?
+            .error_for_status()
+            .context("Got unexpected status code")?
+            .json::<SearchResult>()
+            .await
+            .context("Could not parse search result")?;
+        Ok(result.hits.hits.into_iter().map(|hit| hit.source).collect())
+    }
 }
 
 pub struct MockRagtag {}
@@ -93,6 +167,10 @@ impl ArchiveSite for MockRagtag {
         Ok(false)
     }
 
+    async fn get(&self, _id: &str) -> anyhow::Result<Option<Metadata>> {
+        Ok(None)
+    }
+
     async fn archive(&self, id: &str, metadata: &Metadata) -> anyhow::Result<()> {
         info!("[Mock] Archived video {} with metadata {:?}", id, metadata);
         Ok(())
@@ -145,4 +223,80 @@ mod test {
         m1.assert();
         m2.assert();
     }
+
+    #[tokio::test]
+    async fn test_namespaced_id() {
+        let m1 = mock("GET", "/api/v1/search")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "v".into(),
+                "example.com:a&b#c".into(),
+            ))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"hits":{"total":{"value":1}}}"#)
+            .create();
+        let m2 = mock("PUT", "/api/v2/archive/example.com:a&b%23c%2Fd")
+            .with_status(200)
+            .create();
+
+        let ragtag = Ragtag::new(url::Url::parse(&mockito::server_url()).unwrap(), None)
+            .await
+            .unwrap();
+        assert!(ragtag.is_archived("example.com:a&b#c").await.unwrap());
+        ragtag
+            .archive("example.com:a&b#c/d", &Metadata::default())
+            .await
+            .unwrap();
+
+        m1.assert();
+        m2.assert();
+    }
+
+    #[tokio::test]
+    async fn test_get() {
+        let m = mock("GET", "/api/v1/search")
+            .match_query(mockito::Matcher::UrlEncoded("v".into(), "789".into()))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"hits":{"total":{"value":1},"hits":[{"_source":{"video_id":"789","duration":60,"files":[{"name":"789.mkv","size":1}],"live_chat":"failed"}}]}}"#,
+            )
+            .create();
+
+        let ragtag = Ragtag::new(url::Url::parse(&mockito::server_url()).unwrap(), None)
+            .await
+            .unwrap();
+        let metadata = ragtag.get("789").await.unwrap().unwrap();
+        assert_eq!(metadata.duration, 60);
+        assert_eq!(metadata.files.len(), 1);
+        assert_eq!(
+            metadata.live_chat,
+            Some(super::super::LiveChatStatus::Failed)
+        );
+
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn test_sample() {
+        let m = mock("POST", "/api/v1/search")
+            .match_body(mockito::Matcher::PartialJsonString(
+                r#"{"size":5,"query":{"function_score":{"query":{"match_all":{}}}}}"#.into(),
+            ))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"hits":{"total":{"value":2},"hits":[{"_source":{"video_id":"a"}},{"_source":{"video_id":"b"}}]}}"#,
+            )
+            .create();
+
+        let ragtag = Ragtag::new(url::Url::parse(&mockito::server_url()).unwrap(), None)
+            .await
+            .unwrap();
+        let sample = ragtag.sample(5).await.unwrap();
+        assert_eq!(sample.len(), 2);
+        assert_eq!(sample[1].video_id, "b");
+
+        m.assert();
+    }
 }
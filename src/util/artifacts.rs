@@ -0,0 +1,249 @@
+use anyhow::Context;
+use reqwest::Client;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Where the tools (yt-dlp, ffmpeg, rclone, the POT plugin) are installed
+/// from.
+#[derive(Clone, Debug)]
+pub enum ArtifactSource {
+    /// Fetch the latest releases from GitHub.
+    Upstream,
+    /// Copy from a local directory containing a `manifest.json`.
+    Directory(PathBuf),
+    /// Download from an HTTP mirror serving a `manifest.json`.
+    Mirror(url::Url),
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Manifest {
+    pub artifacts: Vec<ManifestEntry>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ManifestEntry {
+    /// Tool name, e.g. `yt-dlp`, `ffmpeg`, `rclone` or `yt-dlp-get-pot`.
+    pub name: String,
+    /// Target architecture as in `CFG_TARGET_ARCH`. Artifacts without an
+    /// architecture match any target.
+    pub arch: Option<String>,
+    /// File name, relative to the manifest.
+    pub file: String,
+    /// Hex-encoded SHA-256 of the file.
+    pub sha256: String,
+    /// Version of the tool, used to decide whether to update.
+    pub version: Option<String>,
+}
+
+impl ArtifactSource {
+    /// Parse the artifact source setting. An empty string means upstream, a
+    /// string starting with `http://` or `https://` a mirror, and anything
+    /// else a local directory.
+    pub fn parse(source: &str) -> anyhow::Result<Self> {
+        if source.is_empty() {
+            Ok(ArtifactSource::Upstream)
+        } else if source.starts_with("http://") || source.starts_with("https://") {
+            // Make sure relative paths are resolved inside the mirror
+            let source = if source.ends_with('/') {
+                source.to_string()
+            } else {
+                format!("{}/", source)
+            };
+            Ok(ArtifactSource::Mirror(
+                url::Url::parse(&source).context("Could not parse artifact mirror URL")?,
+            ))
+        } else {
+            Ok(ArtifactSource::Directory(PathBuf::from(source)))
+        }
+    }
+
+    pub fn is_upstream(&self) -> bool {
+        matches!(self, ArtifactSource::Upstream)
+    }
+
+    /// Fetch and parse the manifest.
+    pub async fn manifest(&self) -> anyhow::Result<Manifest> {
+        let data = match self {
+            ArtifactSource::Upstream => anyhow::bail!("Upstream releases have no manifest"),
+            ArtifactSource::Directory(dir) => tokio::fs::read(dir.join("manifest.json"))
+                .await
+                .context("Could not read manifest")?,
+            ArtifactSource::Mirror(url) => Client::new()
+                .get(url.join("manifest.json")?)
+                .send()
+                .await
+                .context("Could not fetch manifest")?
+                .error_for_status()
+                .context("Got unexpected status code")?
+                .bytes()
+                .await
+                .context("Could not read manifest")?
+                .to_vec(),
+        };
+        serde_json::from_slice(&data).context("Could not parse manifest")
+    }
+
+    /// Find the manifest entry of a tool for the current architecture.
+    pub async fn entry(&self, name: &str) -> anyhow::Result<ManifestEntry> {
+        let arch = crate::built_info::CFG_TARGET_ARCH;
+        self.manifest()
+            .await?
+            .artifacts
+            .into_iter()
+            .find(|a| a.name == name && a.arch.as_ref().is_none_or(|a| a == arch))
+            .ok_or_else(|| anyhow::anyhow!("No {} artifact for {} in manifest", name, arch))
+    }
+
+    /// Install a tool into `path`. The file is checked against the manifest
+    /// checksum before it is moved into place.
+    pub async fn install(&self, name: &str, path: &Path) -> anyhow::Result<()> {
+        let entry = self.entry(name).await?;
+        let staged_path = path.with_extension("part");
+        let mut staged = tokio::fs::File::create(&staged_path)
+            .await
+            .context("Could not create staging file")?;
+        let mut hasher = Sha256::new();
+
+        match self {
+            ArtifactSource::Upstream => unreachable!(),
+            ArtifactSource::Directory(dir) => {
+                let mut file = tokio::fs::File::open(dir.join(&entry.file))
+                    .await
+                    .with_context(|| format!("Could not open {}", entry.file))?;
+                let mut buf = vec![0; 64 * 1024];
+                loop {
+                    let n = file.read(&mut buf).await?;
+                    if n == 0 {
+                        break;
+                    }
+                    hasher.update(&buf[..n]);
+                    staged.write_all(&buf[..n]).await?;
+                }
+            }
+            ArtifactSource::Mirror(url) => {
+                let mut resp = Client::new()
+                    .get(url.join(&entry.file)?)
+                    .send()
+                    .await
+                    .with_context(|| format!("Could not fetch {}", entry.file))?
+                    .error_for_status()
+                    .context("Got unexpected status code")?;
+                while let Some(chunk) = resp.chunk().await? {
+                    hasher.update(&chunk);
+                    staged.write_all(&chunk).await?;
+                }
+            }
+        }
+        staged.flush().await?;
+
+        let checksum = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        if !checksum.eq_ignore_ascii_case(&entry.sha256) {
+            let _ = tokio::fs::remove_file(&staged_path).await;
+            anyhow::bail!(
+                "Checksum mismatch for {}: expected {}, got {}",
+                entry.file,
+                entry.sha256,
+                checksum
+            );
+        }
+
+        // Make the file executable
+        let mut perms = staged.metadata().await?.permissions();
+        perms.set_mode(0o755);
+        staged.set_permissions(perms).await?;
+        drop(staged);
+
+        tokio::fs::rename(&staged_path, path)
+            .await
+            .context("Could not move artifact into place")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mockito::mock;
+
+    // sha256("hello\n")
+    const HELLO_SHA256: &str = "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03";
+
+    fn manifest(sha256: &str) -> String {
+        format!(
+            r#"{{"artifacts":[{{"name":"hello","file":"hello.bin","sha256":"{}","version":"1.0"}}]}}"#,
+            sha256
+        )
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(ArtifactSource::parse("").unwrap().is_upstream());
+        assert!(matches!(
+            ArtifactSource::parse("/opt/artifacts").unwrap(),
+            ArtifactSource::Directory(_)
+        ));
+        match ArtifactSource::parse("http://mirror.local/tools").unwrap() {
+            ArtifactSource::Mirror(url) => {
+                assert_eq!(
+                    url.join("manifest.json").unwrap().path(),
+                    "/tools/manifest.json"
+                )
+            }
+            _ => panic!("Expected a mirror"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_install_from_directory() {
+        let src = tempfile::tempdir().unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        std::fs::write(src.path().join("manifest.json"), manifest(HELLO_SHA256)).unwrap();
+        std::fs::write(src.path().join("hello.bin"), "hello\n").unwrap();
+
+        let source = ArtifactSource::Directory(src.path().to_path_buf());
+        let path = dest.path().join("hello");
+        source.install("hello", &path).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello\n");
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+
+        // A bad checksum must not replace the existing file
+        std::fs::write(src.path().join("manifest.json"), manifest(&"0".repeat(64))).unwrap();
+        std::fs::write(src.path().join("hello.bin"), "tampered\n").unwrap();
+        assert!(source.install("hello", &path).await.is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello\n");
+    }
+
+    #[tokio::test]
+    async fn test_install_from_mirror() {
+        let m1 = mock("GET", "/tools/manifest.json")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(manifest(HELLO_SHA256))
+            .expect(1)
+            .create();
+        let m2 = mock("GET", "/tools/hello.bin")
+            .with_status(200)
+            .with_body("hello\n")
+            .expect(1)
+            .create();
+
+        let dest = tempfile::tempdir().unwrap();
+        let source = ArtifactSource::parse(&format!("{}/tools", mockito::server_url())).unwrap();
+        let path = dest.path().join("hello");
+        source.install("hello", &path).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello\n");
+
+        m1.assert();
+        m2.assert();
+    }
+}
@@ -0,0 +1,198 @@
+use super::{ArchiveSite, Metadata, MetadataExtractor};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Whether an archived video can still be watched on YouTube.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AvailabilityStatus {
+    Available,
+    Unlisted,
+    Private,
+    /// Removed, or private in a way the API does not tell apart
+    Unavailable,
+}
+
+impl AvailabilityStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AvailabilityStatus::Available => "available",
+            AvailabilityStatus::Unlisted => "unlisted",
+            AvailabilityStatus::Private => "private",
+            AvailabilityStatus::Unavailable => "unavailable",
+        }
+    }
+}
+
+/// A status an archived video was found in, and since when.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AvailabilityChange {
+    pub status: AvailabilityStatus,
+    pub since: String,
+}
+
+/// Upstream availability of an archived video, stored in its metadata.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AvailabilityInfo {
+    pub status: AvailabilityStatus,
+    pub checked_at: String,
+    /// Every status the video was found in, oldest first
+    pub history: Vec<AvailabilityChange>,
+}
+
+impl AvailabilityInfo {
+    /// Record the result of a check at `now`. Returns whether the status
+    /// changed, including the first check of a video.
+    pub fn record(info: &mut Option<Self>, status: AvailabilityStatus, now: &str) -> bool {
+        match info {
+            Some(info) if info.status == status => {
+                info.checked_at = now.into();
+                false
+            }
+            Some(info) => {
+                info.status = status;
+                info.checked_at = now.into();
+                info.history.push(AvailabilityChange {
+                    status,
+                    since: now.into(),
+                });
+                true
+            }
+            None => {
+                *info = Some(Self {
+                    status,
+                    checked_at: now.into(),
+                    history: vec![AvailabilityChange {
+                        status,
+                        since: now.into(),
+                    }],
+                });
+                true
+            }
+        }
+    }
+}
+
+/// Periodically checks a sample of archived videos for removal upstream and
+/// reports changes to the archive site.
+pub struct AvailabilityMonitor {
+    archive_site: Box<dyn ArchiveSite>,
+    metadata_extractor: Box<dyn MetadataExtractor>,
+    sample_size: usize,
+    interval: Duration,
+}
+
+impl AvailabilityMonitor {
+    pub fn new(
+        archive_site: Box<dyn ArchiveSite>,
+        metadata_extractor: Box<dyn MetadataExtractor>,
+        sample_size: usize,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            archive_site,
+            metadata_extractor,
+            sample_size,
+            interval,
+        }
+    }
+
+    pub async fn run_forever(&self) {
+        loop {
+            tokio::time::sleep(self.interval).await;
+            if let Err(e) = self.check().await {
+                warn!("Could not check availability of archived videos: {:#}", e);
+            }
+        }
+    }
+
+    /// Check a sample of archived videos. Returns how many changed status.
+    pub async fn check(&self) -> anyhow::Result<usize> {
+        // Only YouTube videos can be looked up, others have namespaced IDs
+        let mut sample: Vec<Metadata> = self
+            .archive_site
+            .sample(self.sample_size)
+            .await
+            .context("Could not sample archived videos")?
+            .into_iter()
+            .filter(|m| !m.video_id.contains(':'))
+            .collect();
+        let ids: Vec<&str> = sample.iter().map(|m| m.video_id.as_str()).collect();
+        info!("Checking availability of {} archived videos", ids.len());
+        let statuses = self
+            .metadata_extractor
+            .availability(&ids)
+            .await
+            .context("Could not look up availability")?;
+
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut changed = 0;
+        for metadata in &mut sample {
+            let status = match statuses.get(&metadata.video_id) {
+                Some(status) => *status,
+                None => continue,
+            };
+            super::metrics::counter_inc(
+                "archivebot_availability_checks_total",
+                &[("status", status.as_str())],
+            );
+            if !AvailabilityInfo::record(&mut metadata.availability, status, &now) {
+                continue;
+            }
+            info!("Video {} is now {}", metadata.video_id, status.as_str());
+            self.archive_site
+                .archive(&metadata.video_id, metadata)
+                .await
+                .context("Could not update video in archive")?;
+            changed += 1;
+        }
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let mut info = None;
+        assert!(AvailabilityInfo::record(
+            &mut info,
+            AvailabilityStatus::Available,
+            "2024-01-01T00:00:00Z"
+        ));
+        assert!(!AvailabilityInfo::record(
+            &mut info,
+            AvailabilityStatus::Available,
+            "2024-02-01T00:00:00Z"
+        ));
+        assert!(AvailabilityInfo::record(
+            &mut info,
+            AvailabilityStatus::Unavailable,
+            "2024-03-01T00:00:00Z"
+        ));
+
+        let info = info.unwrap();
+        assert_eq!(info.status, AvailabilityStatus::Unavailable);
+        assert_eq!(info.checked_at, "2024-03-01T00:00:00Z");
+        assert_eq!(
+            info.history,
+            vec![
+                AvailabilityChange {
+                    status: AvailabilityStatus::Available,
+                    since: "2024-01-01T00:00:00Z".into(),
+                },
+                AvailabilityChange {
+                    status: AvailabilityStatus::Unavailable,
+                    since: "2024-03-01T00:00:00Z".into(),
+                },
+            ]
+        );
+        assert_eq!(
+            serde_json::to_string(&info.status).unwrap(),
+            r#""unavailable""#
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use super::metrics;
+use super::DownloadFailure;
+use std::sync::Mutex;
+use tokio::time::{sleep_until, Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    /// Tasks are consumed normally.
+    Closed,
+    /// YouTube is blocking us, no tasks are consumed until the given time.
+    Open(Instant),
+    /// The cooling-off period is over and a single canary task decides
+    /// whether to close or re-open the breaker.
+    HalfOpen,
+}
+
+impl State {
+    fn as_str(&self) -> &'static str {
+        match self {
+            State::Closed => "closed",
+            State::Open(_) => "open",
+            State::HalfOpen => "half_open",
+        }
+    }
+}
+
+struct Inner {
+    state: State,
+    cooldown: Duration,
+}
+
+/// Stops task consumption when YouTube starts detecting the bot or rate
+/// limiting it, so that failing tasks do not make the block worse.
+pub struct CircuitBreaker {
+    inner: Mutex<Inner>,
+    min_cooldown: Duration,
+    max_cooldown: Duration,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new(
+            Duration::from_secs(15 * 60),
+            Duration::from_secs(4 * 60 * 60),
+        )
+    }
+}
+
+impl CircuitBreaker {
+    /// Create a closed circuit breaker. The cooling-off period starts at
+    /// `min_cooldown` and doubles every time the canary fails, up to
+    /// `max_cooldown`.
+    pub fn new(min_cooldown: Duration, max_cooldown: Duration) -> Self {
+        let breaker = Self {
+            inner: Mutex::new(Inner {
+                state: State::Closed,
+                cooldown: min_cooldown,
+            }),
+            min_cooldown,
+            max_cooldown,
+        };
+        breaker.report(State::Closed);
+        breaker
+    }
+
+    fn report(&self, state: State) {
+        for s in ["closed", "open", "half_open"] {
+            metrics::gauge_set(
+                "archivebot_circuit_breaker_state",
+                &[("state", s)],
+                if s == state.as_str() { 1.0 } else { 0.0 },
+            );
+        }
+    }
+
+    /// Whether tasks are currently held back.
+    pub fn is_open(&self) -> bool {
+        matches!(self.inner.lock().unwrap().state, State::Open(_))
+    }
+
+    /// Wait until a task may be consumed. Once the cooling-off period is over,
+    /// the next task acts as the canary.
+    pub async fn wait(&self) {
+        let until = match self.inner.lock().unwrap().state {
+            State::Open(until) => until,
+            _ => return,
+        };
+
+        info!(
+            "Circuit breaker open, pausing for {} seconds",
+            until.saturating_duration_since(Instant::now()).as_secs()
+        );
+        sleep_until(until).await;
+
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state == State::Open(until) {
+            info!("Circuit breaker half-open, probing with a canary task");
+            inner.state = State::HalfOpen;
+            self.report(inner.state);
+        }
+    }
+
+    /// Record the outcome of a download. Bot detection and rate limiting trip
+    /// the breaker, a successful download closes it, and other failures leave
+    /// it as it is.
+    pub fn record(&self, failure: Option<DownloadFailure>) {
+        let mut inner = self.inner.lock().unwrap();
+        match failure {
+            Some(DownloadFailure::BotCheck) | Some(DownloadFailure::RateLimited) => {
+                if inner.state == State::HalfOpen {
+                    inner.cooldown = (inner.cooldown * 2).min(self.max_cooldown);
+                }
+                warn!(
+                    "Tripping circuit breaker for {} seconds ({})",
+                    inner.cooldown.as_secs(),
+                    failure.unwrap()
+                );
+                inner.state = State::Open(Instant::now() + inner.cooldown);
+                metrics::counter_inc("archivebot_circuit_breaker_trips_total", &[]);
+            }
+            None => {
+                if inner.state != State::Closed {
+                    info!("Canary task succeeded, closing circuit breaker");
+                }
+                inner.state = State::Closed;
+                inner.cooldown = self.min_cooldown;
+            }
+            Some(_) => {}
+        }
+        self.report(inner.state);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_breaker() {
+        let breaker = CircuitBreaker::new(Duration::from_millis(50), Duration::from_millis(80));
+        breaker.wait().await;
+
+        // Unrelated failures do not trip the breaker
+        breaker.record(Some(DownloadFailure::Private));
+        assert!(!breaker.is_open());
+
+        breaker.record(Some(DownloadFailure::BotCheck));
+        assert!(breaker.is_open());
+        let start = Instant::now();
+        breaker.wait().await;
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(breaker.inner.lock().unwrap().state, State::HalfOpen);
+
+        // A failed canary re-opens the breaker for longer, up to the maximum
+        breaker.record(Some(DownloadFailure::RateLimited));
+        assert_eq!(
+            breaker.inner.lock().unwrap().cooldown,
+            Duration::from_millis(80)
+        );
+        breaker.wait().await;
+
+        // A successful canary closes it and resets the cooldown
+        breaker.record(None);
+        assert_eq!(breaker.inner.lock().unwrap().state, State::Closed);
+        assert_eq!(
+            breaker.inner.lock().unwrap().cooldown,
+            Duration::from_millis(50)
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::path::Path;
+
+/// How the last comment fetch of a video went.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommentsStatus {
+    Present,
+    /// Fetching the comments took longer than the profile allows
+    TimedOut,
+    Failed,
+}
+
+/// The archived comments of a video, stored in its metadata.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CommentsInfo {
+    pub status: CommentsStatus,
+    /// Number of comments archived over all fetches
+    pub count: u64,
+    /// IDs of the archived comments, which later fetches skip. Comment
+    /// timestamps are too coarse to tell which comments are new.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub ids: BTreeSet<String>,
+    pub fetched_at: String,
+}
+
+impl CommentsInfo {
+    pub fn new(status: CommentsStatus) -> Self {
+        Self {
+            status,
+            count: 0,
+            ids: BTreeSet::new(),
+            fetched_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// Combine the result of a later fetch with this one.
+    pub fn merge(&mut self, later: CommentsInfo) {
+        self.status = later.status;
+        self.count += later.count;
+        self.ids.extend(later.ids);
+        self.fetched_at = later.fetched_at;
+    }
+}
+
+/// Name of the comments artifact of a video. Incremental fetches get their
+/// own file so earlier ones are never overwritten.
+pub fn file_name(video_id: &str, incremental: bool) -> String {
+    match incremental {
+        false => format!("{}.comments.jsonl.gz", video_id),
+        true => format!(
+            "{}.comments.{}.jsonl.gz",
+            video_id,
+            chrono::Utc::now().timestamp()
+        ),
+    }
+}
+
+/// Write the comments in a yt-dlp info.json to `dest` as gzipped JSONL, one
+/// comment per line, skipping the comments in `archived`.
+pub fn write(
+    info_json: &Path,
+    dest: &Path,
+    archived: &BTreeSet<String>,
+) -> anyhow::Result<CommentsInfo> {
+    let info: Value = serde_json::from_reader(std::io::BufReader::new(
+        std::fs::File::open(info_json).context("Could not open info.json")?,
+    ))
+    .context("Could not parse info.json")?;
+    let comments = info["comments"].as_array().cloned().unwrap_or_default();
+
+    let mut out = super::gzip_writer(dest)?;
+    let mut result = CommentsInfo::new(CommentsStatus::Present);
+    for comment in comments {
+        if let Some(id) = comment["id"].as_str() {
+            if archived.contains(id) || !result.ids.insert(id.to_string()) {
+                continue;
+            }
+        }
+        serde_json::to_writer(&mut out, &comment)?;
+        out.write_all(b"\n")?;
+        result.count += 1;
+    }
+    out.finish()?.flush()?;
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let info_json = dir.path().join("abc.info.json");
+        std::fs::write(
+            &info_json,
+            r#"{"id":"abc","comments":[
+                {"id":"1","text":"first","timestamp":100},
+                {"id":"2","parent":"1","text":"reply","timestamp":200},
+                {"id":"3","text":"new","timestamp":300}
+            ]}"#,
+        )
+        .unwrap();
+
+        let dest = dir.path().join("abc.comments.jsonl.gz");
+        let info = write(&info_json, &dest, &BTreeSet::new()).unwrap();
+        assert_eq!(info.count, 3);
+        assert_eq!(info.ids.len(), 3);
+
+        // A later fetch sees an older comment with a shifted timestamp
+        std::fs::write(
+            &info_json,
+            r#"{"id":"abc","comments":[
+                {"id":"4","text":"new","timestamp":100},
+                {"id":"1","text":"first","timestamp":300},
+                {"id":"2","parent":"1","text":"reply","timestamp":300}
+            ]}"#,
+        )
+        .unwrap();
+        let info = write(&info_json, &dest, &info.ids).unwrap();
+        assert_eq!(info.count, 1);
+        assert_eq!(info.ids, BTreeSet::from(["4".to_string()]));
+        let mut lines = String::new();
+        GzDecoder::new(std::fs::File::open(&dest).unwrap())
+            .read_to_string(&mut lines)
+            .unwrap();
+        assert_eq!(lines.lines().count(), 1);
+        assert!(lines.contains(r#""text":"new""#));
+
+        let mut merged = CommentsInfo::new(CommentsStatus::Failed);
+        merged.merge(info);
+        assert_eq!(merged.status, CommentsStatus::Present);
+        assert_eq!(merged.count, 1);
+        assert_eq!(merged.ids.len(), 1);
+    }
+}
@@ -0,0 +1,127 @@
+use super::metrics;
+use anyhow::Context;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Netscape cookie files used to download members-only and age-restricted
+/// videos, and which channels use them.
+#[derive(Debug, Default)]
+pub struct CookieJars {
+    jars: HashMap<String, PathBuf>,
+    channels: HashMap<String, String>,
+}
+
+impl CookieJars {
+    /// Parse the cookie jars from their config values. `jars` is a JSON object
+    /// of jar names to cookie file paths and `channels` a JSON object of
+    /// channel IDs to jar names. Both may be empty.
+    pub fn parse(jars: &str, channels: &str) -> anyhow::Result<Self> {
+        let mut parsed = Self::default();
+        if !jars.is_empty() {
+            parsed.jars = serde_json::from_str(jars).context("Could not parse cookie jars")?;
+        }
+        if !channels.is_empty() {
+            parsed.channels =
+                serde_json::from_str(channels).context("Could not parse channel cookie jars")?;
+        }
+
+        for name in parsed.channels.values() {
+            parsed.get(name)?;
+        }
+        Ok(parsed)
+    }
+
+    fn get(&self, name: &str) -> anyhow::Result<&PathBuf> {
+        self.jars
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown cookie jar {}", name))
+    }
+
+    pub fn has_channel_jars(&self) -> bool {
+        !self.channels.is_empty()
+    }
+
+    /// Pick the cookie jar for a download: the requested one if any,
+    /// otherwise the one configured for the channel. Most downloads use none.
+    pub fn select<'a>(
+        &'a self,
+        requested: Option<&'a str>,
+        channel_id: Option<&str>,
+    ) -> anyhow::Result<Option<(&'a str, &'a PathBuf)>> {
+        let name = match requested
+            .or_else(|| channel_id.and_then(|c| self.channels.get(c).map(|j| j.as_str())))
+        {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        Ok(Some((name, self.get(name)?)))
+    }
+
+    /// Copy a cookie jar to a temporary file. yt-dlp writes cookies back when
+    /// it exits, which would fail on read-only secret mounts, and the copy
+    /// must stay out of the upload directory. Every call makes a new copy, so
+    /// yt-dlp processes running at the same time never share one.
+    pub async fn checkout(&self, path: &PathBuf) -> anyhow::Result<tempfile::NamedTempFile> {
+        let file = tempfile::NamedTempFile::new_in(super::get_cache_dir().await?)
+            .context("Could not create temporary cookie file")?;
+        tokio::fs::copy(path, file.path())
+            .await
+            .with_context(|| format!("Could not copy cookie file {}", path.display()))?;
+        Ok(file)
+    }
+
+    /// Record whether the cookies of a jar were accepted.
+    pub fn report(&self, name: &str, valid: bool) {
+        if !valid {
+            warn!("Cookies in jar {} are no longer valid", name);
+        }
+        metrics::gauge_set(
+            "archivebot_cookie_jar_valid",
+            &[("jar", name)],
+            if valid { 1.0 } else { 0.0 },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_select() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("member.txt");
+        std::fs::write(&path, "# Netscape HTTP Cookie File\n").unwrap();
+
+        let jars = CookieJars::parse(
+            &serde_json::json!({ "member": path }).to_string(),
+            r#"{"UCmember": "member"}"#,
+        )
+        .unwrap();
+
+        assert!(jars.select(None, None).unwrap().is_none());
+        assert!(jars.select(None, Some("UCother")).unwrap().is_none());
+        let (name, jar) = jars.select(None, Some("UCmember")).unwrap().unwrap();
+        assert_eq!(name, "member");
+        assert_eq!(jar, &path);
+        assert!(jars.select(Some("missing"), None).is_err());
+
+        let copy = jars.checkout(jar).await.unwrap();
+        assert_ne!(copy.path(), path);
+        assert_eq!(
+            std::fs::read_to_string(copy.path()).unwrap(),
+            "# Netscape HTTP Cookie File\n"
+        );
+
+        // The video and live chat processes each write back their own copy
+        let other = jars.checkout(jar).await.unwrap();
+        assert_ne!(other.path(), copy.path());
+        std::fs::write(copy.path(), "written back").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(other.path()).unwrap(),
+            "# Netscape HTTP Cookie File\n"
+        );
+
+        assert!(CookieJars::parse("", r#"{"UC": "typo"}"#).is_err());
+    }
+}
@@ -0,0 +1,324 @@
+use super::artifacts::ArtifactSource;
+use super::tools::Tool;
+use super::ytdl::YTDL;
+use super::{DownloadFailure, MediaValidator, Metadata, SelfInstallable};
+use anyhow::Context;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// Extensions of the merged media file written by yt-dlp.
+static MEDIA_EXTENSIONS: &[&str] = &[
+    "mp4", "mkv", "webm", "mov", "flv", "m4a", "mka", "opus", "ogg", "mp3",
+];
+
+#[derive(Debug, Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeStream {
+    codec_type: String,
+    width: Option<i32>,
+    height: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+}
+
+/// Validates downloaded media with ffprobe before it gets uploaded.
+pub struct FFProbe {
+    ffprobe: Tool,
+    ffprobe_path: PathBuf,
+    artifacts: ArtifactSource,
+    /// Allowed difference between the container duration and the duration
+    /// reported by YouTube.
+    duration_tolerance: f64,
+}
+
+impl FFProbe {
+    pub async fn new(
+        ffprobe: Tool,
+        artifacts: ArtifactSource,
+        duration_tolerance: f64,
+    ) -> anyhow::Result<Self> {
+        let ffprobe = Self {
+            ffprobe_path: ffprobe.resolve().await?,
+            ffprobe,
+            artifacts,
+            duration_tolerance,
+        };
+
+        if !ffprobe.is_installed().await {
+            ffprobe
+                .install()
+                .await
+                .context("Could not install ffprobe")?;
+        }
+
+        let version = ffprobe
+            .ffprobe
+            .check_version(&ffprobe.ffprobe_path, "-version")
+            .await?;
+        info!("Using ffprobe {}", version);
+
+        Ok(ffprobe)
+    }
+
+    async fn probe(&self, path: &Path) -> anyhow::Result<ProbeOutput> {
+        let output = Command::new(&self.ffprobe_path)
+            .kill_on_drop(true)
+            .args([
+                "-v",
+                "error",
+                "-print_format",
+                "json",
+                "-show_format",
+                "-show_streams",
+            ])
+            .arg(path)
+            .output()
+            .await
+            .context("Could not run ffprobe")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "ffprobe exited with status {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        serde_json::from_slice(&output.stdout).context("Could not parse ffprobe output")
+    }
+}
+
+/// Find the merged media file of a video in the workdir.
+fn find_media(workdir: &Path, video_id: &str) -> anyhow::Result<PathBuf> {
+    workdir
+        .read_dir()
+        .context("Could not read workdir")?
+        .find_map(|entry| {
+            let path = entry.ok()?.path();
+            let stem = path.file_stem()?.to_str()?;
+            let ext = path.extension()?.to_str()?;
+            if stem == video_id && MEDIA_EXTENSIONS.contains(&ext) {
+                Some(path)
+            } else {
+                None
+            }
+        })
+        .ok_or_else(|| anyhow::anyhow!("Could not find media file"))
+}
+
+/// Check the probed media against the metadata of the video. Returns what is
+/// wrong with it, if anything. Only the streams the selected format has are
+/// required, so audio-only profiles pass.
+fn check(probe: &ProbeOutput, metadata: &Metadata, duration_tolerance: f64) -> Option<String> {
+    // yt-dlp writes "none" for the codec a format does not have
+    let has = |codec: Option<&String>| codec.is_some_and(|c| c != "none");
+    let format = metadata.format.as_ref();
+    let wants_video = has(format.and_then(|f| f.vcodec.as_ref()));
+    let wants_audio = has(format.and_then(|f| f.acodec.as_ref()));
+
+    let video = probe.streams.iter().find(|s| s.codec_type == "video");
+    if probe.streams.is_empty() {
+        return Some("no streams".into());
+    }
+    if wants_audio && !probe.streams.iter().any(|s| s.codec_type == "audio") {
+        return Some("no audio stream".into());
+    }
+    if wants_video && video.is_none() {
+        return Some("no video stream".into());
+    }
+
+    if metadata.duration > 0 {
+        let duration = probe
+            .format
+            .as_ref()
+            .and_then(|f| f.duration.as_deref())
+            .and_then(|d| d.parse::<f64>().ok());
+        match duration {
+            None => return Some("unknown duration".into()),
+            Some(duration) if (duration - metadata.duration as f64).abs() > duration_tolerance => {
+                return Some(format!(
+                    "duration {:.1}s does not match expected {}s",
+                    duration, metadata.duration
+                ))
+            }
+            Some(_) => {}
+        }
+    }
+
+    // The top-level resolution of the info.json is that of the selected
+    // format, and only set if it has one
+    if let Some(video) = video {
+        if wants_video
+            && metadata.width > 0
+            && metadata.height > 0
+            && (video.width, video.height) != (Some(metadata.width), Some(metadata.height))
+        {
+            return Some(format!(
+                "resolution {}x{} does not match expected {}x{}",
+                video.width.unwrap_or(0),
+                video.height.unwrap_or(0),
+                metadata.width,
+                metadata.height
+            ));
+        }
+    }
+
+    None
+}
+
+#[async_trait]
+impl MediaValidator for FFProbe {
+    async fn validate(&self, workdir: &Path, metadata: &Metadata) -> anyhow::Result<()> {
+        let invalid =
+            |reason: String| anyhow::Error::new(DownloadFailure::InvalidMedia).context(reason);
+
+        let path = find_media(workdir, &metadata.video_id).map_err(|e| invalid(e.to_string()))?;
+        let probe = self
+            .probe(&path)
+            .await
+            .map_err(|e| invalid(format!("{:#}", e)))?;
+        if let Some(reason) = check(&probe, metadata, self.duration_tolerance) {
+            return Err(invalid(format!(
+                "{} looks broken: {}",
+                path.display(),
+                reason
+            )));
+        }
+
+        debug!("{} passed validation", path.display());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SelfInstallable for FFProbe {
+    async fn is_installed(&self) -> bool {
+        Command::new(&self.ffprobe_path)
+            .arg("-version")
+            .output()
+            .await
+            .is_ok()
+    }
+
+    async fn install(&self) -> anyhow::Result<()> {
+        if !self.ffprobe.is_managed() {
+            anyhow::bail!("ffprobe is not managed by the bot and could not be run");
+        }
+
+        info!("Installing ffprobe");
+
+        if !self.artifacts.is_upstream() {
+            return self.artifacts.install("ffprobe", &self.ffprobe_path).await;
+        }
+
+        let url = match crate::built_info::CFG_TARGET_ARCH {
+            "x86_64" => "https://github.com/eugeneware/ffmpeg-static/releases/download/b5.0.1/ffprobe-linux-x64",
+            "aarch64" => "https://github.com/eugeneware/ffmpeg-static/releases/download/b5.0.1/ffprobe-linux-arm64",
+            _ => anyhow::bail!("Unsupported architecture"),
+        };
+        YTDL::install_binary(url, &self.ffprobe_path).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn metadata() -> Metadata {
+        Metadata {
+            video_id: "abc".into(),
+            duration: 600,
+            width: 1920,
+            height: 1080,
+            format: Some(super::super::MetadataFormat {
+                vcodec: Some("avc1.640028".into()),
+                acodec: Some("mp4a.40.2".into()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn probe(duration: &str, streams: &[(&str, i32, i32)]) -> ProbeOutput {
+        ProbeOutput {
+            streams: streams
+                .iter()
+                .map(|(codec_type, width, height)| ProbeStream {
+                    codec_type: codec_type.to_string(),
+                    width: Some(*width),
+                    height: Some(*height),
+                })
+                .collect(),
+            format: Some(ProbeFormat {
+                duration: Some(duration.into()),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let metadata = metadata();
+        let good = [("video", 1920, 1080), ("audio", 0, 0)];
+        assert_eq!(check(&probe("601.2", &good), &metadata, 5.0), None);
+        assert!(check(&probe("312.0", &good), &metadata, 5.0)
+            .unwrap()
+            .contains("duration"));
+        assert!(check(
+            &probe("600.0", &[("video", 1280, 720), ("audio", 0, 0)]),
+            &metadata,
+            5.0
+        )
+        .unwrap()
+        .contains("resolution"));
+        assert_eq!(
+            check(&probe("600.0", &[("video", 1920, 1080)]), &metadata, 5.0).unwrap(),
+            "no audio stream"
+        );
+        assert_eq!(
+            check(&probe("600.0", &[("audio", 0, 0)]), &metadata, 5.0).unwrap(),
+            "no video stream"
+        );
+
+        // Audio-only formats need no video stream and have no resolution
+        let audio_only = Metadata {
+            width: 0,
+            height: 0,
+            format: Some(super::super::MetadataFormat {
+                vcodec: Some("none".into()),
+                acodec: Some("opus".into()),
+                ..Default::default()
+            }),
+            ..self::metadata()
+        };
+        assert_eq!(
+            check(&probe("600.0", &[("audio", 0, 0)]), &audio_only, 5.0),
+            None
+        );
+        assert_eq!(
+            check(&probe("600.0", &[]), &audio_only, 5.0).unwrap(),
+            "no streams"
+        );
+    }
+
+    #[test]
+    fn test_find_media() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["abc.info.json", "abc.live_chat.json", "abc.webp", "abc.mkv"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        assert_eq!(
+            find_media(dir.path(), "abc").unwrap(),
+            dir.path().join("abc.mkv")
+        );
+        assert!(find_media(dir.path(), "other").is_err());
+    }
+}
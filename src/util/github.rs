@@ -22,26 +22,56 @@ static USER_AGENT: &str = concat!(
     ")"
 );
 
+static GITHUB_API_URL: &str = "https://api.github.com";
+
 pub async fn get_latest_release(repo: &str, client: Option<Client>) -> anyhow::Result<Release> {
-    let url = format!("https://api.github.com/repos/{}/releases/latest", repo);
+    get_latest_release_from(GITHUB_API_URL, repo, client).await
+}
+
+/// Get the latest release of `repo` from the GitHub API at `api_url`.
+pub async fn get_latest_release_from(
+    api_url: &str,
+    repo: &str,
+    client: Option<Client>,
+) -> anyhow::Result<Release> {
+    let url = format!("{}/repos/{}/releases/latest", api_url, repo);
 
-    let client = client.unwrap_or_else(Client::new);
+    let client = client.unwrap_or_default();
     let req = client
         .get(&url)
         .header("Accept", "application/vnd.github+json")
         .header("User-Agent", USER_AGENT)
         .build()?;
 
-    Ok(client.execute(req).await?.json().await?)
+    Ok(client
+        .execute(req)
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use mockito::mock;
 
     #[tokio::test]
     async fn test_get_latest_release() {
-        let release = get_latest_release("yt-dlp/yt-dlp", None).await.unwrap();
+        let _m = mock("GET", "/repos/yt-dlp/yt-dlp/releases/latest")
+            .match_header("user-agent", USER_AGENT)
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"tag_name":"2025.10.22","assets":[
+                    {"name":"yt-dlp","browser_download_url":"https://github.com/yt-dlp/yt-dlp/releases/download/2025.10.22/yt-dlp"},
+                    {"name":"yt-dlp.exe","browser_download_url":"https://github.com/yt-dlp/yt-dlp/releases/download/2025.10.22/yt-dlp.exe"}
+                ]}"#,
+            )
+            .create();
+        let release = get_latest_release_from(&mockito::server_url(), "yt-dlp/yt-dlp", None)
+            .await
+            .unwrap();
         // yt-dlp creates releases with the format yyyy.mm.dd -> 10 chars
         assert_eq!(release.tag_name.len(), 10, "Unexpected tag name length");
         assert!(!release.assets.is_empty(), "No assets found");
@@ -57,5 +87,11 @@ mod test {
             "Missing yt-dlp.exe"
         );
         assert!(asset_names.iter().any(|n| n == "yt-dlp"), "Missing yt-dlp");
+
+        assert!(
+            get_latest_release_from(&mockito::server_url(), "missing/repo", None)
+                .await
+                .is_err()
+        );
     }
 }
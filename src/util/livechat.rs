@@ -0,0 +1,329 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// One normalised live chat event.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ChatMessage {
+    /// Offset from the start of the video in milliseconds
+    pub time_ms: i64,
+    /// Wall clock time of the message in microseconds since the epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp_usec: Option<i64>,
+    /// `text`, `superchat`, `sticker`, `membership` or `gift_membership`
+    pub kind: &'static str,
+    pub author: String,
+    pub author_channel_id: String,
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    /// Number of memberships gifted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gifts: Option<u64>,
+}
+
+/// Summary of a live chat, stored in the video's metadata.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct LiveChatStats {
+    pub messages: u64,
+    pub unique_chatters: u64,
+    pub superchats: u64,
+    /// Super chat and sticker totals per currency
+    pub superchat_totals: BTreeMap<String, f64>,
+    pub memberships: u64,
+    /// Memberships gifted by chatters
+    #[serde(default)]
+    pub gifted_memberships: u64,
+    /// Lines of the raw chat that could not be parsed and were skipped
+    #[serde(default)]
+    pub malformed_lines: u64,
+}
+
+impl LiveChatStats {
+    fn add(&mut self, message: &ChatMessage, chatters: &mut HashSet<String>) {
+        self.messages += 1;
+        if chatters.insert(message.author_channel_id.clone()) {
+            self.unique_chatters += 1;
+        }
+        match message.kind {
+            "superchat" | "sticker" => {
+                self.superchats += 1;
+                if let (Some(amount), Some(currency)) = (message.amount, &message.currency) {
+                    *self.superchat_totals.entry(currency.clone()).or_default() += amount;
+                }
+            }
+            "membership" => self.memberships += 1,
+            "gift_membership" => self.gifted_memberships += message.gifts.unwrap_or(1),
+            _ => {}
+        }
+    }
+}
+
+/// Join the runs of a YouTube formatted string, using the shortcut of custom
+/// emoji.
+fn text(value: &Value) -> String {
+    if let Some(text) = value["simpleText"].as_str() {
+        return text.into();
+    }
+    value["runs"]
+        .as_array()
+        .map(|runs| {
+            runs.iter()
+                .filter_map(|run| {
+                    run["text"]
+                        .as_str()
+                        .or_else(|| run["emoji"]["shortcuts"][0].as_str())
+                        .or_else(|| run["emoji"]["emojiId"].as_str())
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Split a purchase amount such as `$5.00`, `¥1,000` or `CA$20.00` into the
+/// amount and the currency.
+fn parse_amount(amount: &str) -> Option<(f64, String)> {
+    let start = amount.find(|c: char| c.is_ascii_digit())?;
+    let currency = amount[..start].trim();
+    let value = amount[start..].trim().replace(',', "").parse().ok()?;
+    if currency.is_empty() {
+        return None;
+    }
+    Some((value, currency.into()))
+}
+
+/// The number in a gift announcement such as `Gifted 5 Members memberships`.
+fn parse_gifts(text: &str) -> Option<u64> {
+    text.split_whitespace().find_map(|word| word.parse().ok())
+}
+
+fn parse_i64(value: &Value) -> Option<i64> {
+    value.as_str().and_then(|v| v.parse().ok())
+}
+
+/// Normalise one line of yt-dlp's live chat output. Lines can hold several
+/// actions, most of which are not chat messages.
+fn parse_line(line: &str) -> anyhow::Result<Vec<ChatMessage>> {
+    let value: Value = serde_json::from_str(line).context("Could not parse live chat line")?;
+    let replay = &value["replayChatItemAction"];
+    let time_ms = parse_i64(&replay["videoOffsetTimeMsec"]).unwrap_or(0);
+
+    let actions = replay["actions"].as_array().cloned().unwrap_or_default();
+    Ok(actions
+        .iter()
+        .filter_map(|action| {
+            let item = action["addChatItemAction"]["item"].as_object()?;
+            let (kind, renderer) = item.iter().find_map(|(name, renderer)| {
+                let kind = match name.as_str() {
+                    "liveChatTextMessageRenderer" => "text",
+                    "liveChatPaidMessageRenderer" => "superchat",
+                    "liveChatPaidStickerRenderer" => "sticker",
+                    "liveChatMembershipItemRenderer" => "membership",
+                    "liveChatSponsorshipsGiftPurchaseAnnouncementRenderer" => "gift_membership",
+                    _ => return None,
+                };
+                Some((kind, renderer))
+            })?;
+            // Gift announcements keep the author and text in a header
+            let (author, message_renderer) = match kind {
+                "gift_membership" => {
+                    let header = &renderer["header"]["liveChatSponsorshipsHeaderRenderer"];
+                    (text(&header["authorName"]), &header["primaryText"])
+                }
+                _ => (text(&renderer["authorName"]), &renderer["message"]),
+            };
+
+            let (amount, currency) = renderer["purchaseAmountText"]["simpleText"]
+                .as_str()
+                .and_then(parse_amount)
+                .map_or((None, None), |(a, c)| (Some(a), Some(c)));
+            let message = match kind {
+                // Membership events keep their message in the header
+                "membership" => [text(&renderer["headerSubtext"]), text(&renderer["message"])]
+                    .into_iter()
+                    .filter(|t| !t.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                _ => text(message_renderer),
+            };
+            let gifts = (kind == "gift_membership")
+                .then(|| parse_gifts(&message))
+                .flatten();
+
+            Some(ChatMessage {
+                time_ms,
+                timestamp_usec: parse_i64(&renderer["timestampUsec"]),
+                kind,
+                author,
+                author_channel_id: renderer["authorExternalChannelId"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .into(),
+                text: message,
+                amount,
+                currency,
+                gifts,
+            })
+        })
+        .collect())
+}
+
+/// Convert a raw live chat file into `<id>.live_chat.jsonl.gz` next to it.
+/// The raw file is kept as it is, since the archive site replays it. On
+/// failure the half-written normalised file is removed.
+fn process_file(raw: &Path) -> anyhow::Result<LiveChatStats> {
+    let name = raw
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Invalid live chat file name"))?;
+    let normalised_path =
+        raw.with_file_name(name.replace(".live_chat.json", ".live_chat.jsonl.gz"));
+
+    let res = convert(raw, &normalised_path);
+    if res.is_err() {
+        let _ = std::fs::remove_file(&normalised_path);
+    }
+    res
+}
+
+fn convert(raw: &Path, normalised_path: &Path) -> anyhow::Result<LiveChatStats> {
+    let reader = BufReader::new(std::fs::File::open(raw).context("Could not open live chat")?);
+    let mut normalised = super::gzip_writer(normalised_path)?;
+    let mut stats = LiveChatStats::default();
+    let mut chatters = HashSet::new();
+
+    for line in reader.lines() {
+        let line = line.context("Could not read live chat")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        // A truncated or corrupt line should not cost the rest of the chat
+        let messages = match parse_line(&line) {
+            Ok(messages) => messages,
+            Err(e) => {
+                debug!("Skipping live chat line: {:#}", e);
+                stats.malformed_lines += 1;
+                continue;
+            }
+        };
+        for message in messages {
+            stats.add(&message, &mut chatters);
+            serde_json::to_writer(&mut normalised, &message)?;
+            normalised.write_all(b"\n")?;
+        }
+    }
+    if stats.malformed_lines > 0 {
+        warn!(
+            "Skipped {} malformed live chat lines",
+            stats.malformed_lines
+        );
+        super::metrics::counter_add(
+            "archivebot_live_chat_malformed_lines_total",
+            &[],
+            stats.malformed_lines as f64,
+        );
+    }
+
+    normalised.finish()?.flush()?;
+    Ok(stats)
+}
+
+/// Process the live chat in the workdir, if any. Returns its stats.
+pub async fn process(workdir: &Path) -> anyhow::Result<Option<LiveChatStats>> {
+    let raw: Option<PathBuf> = workdir
+        .read_dir()
+        .context("Could not read workdir")?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.ends_with(".live_chat.json"))
+        });
+    let raw = match raw {
+        Some(raw) => raw,
+        None => return Ok(None),
+    };
+
+    info!("Processing live chat {}", raw.display());
+    let stats = tokio::task::spawn_blocking(move || process_file(&raw))
+        .await
+        .context("Live chat processing panicked")??;
+    Ok(Some(stats))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    static CHAT: &str = r#"{"replayChatItemAction":{"actions":[{"addChatItemAction":{"item":{"liveChatTextMessageRenderer":{"message":{"runs":[{"text":"hello "},{"emoji":{"emojiId":"x","shortcuts":[":wave:"]}}]},"authorName":{"simpleText":"Alice"},"authorExternalChannelId":"UCalice","timestampUsec":"1700000000000000"}}}}],"videoOffsetTimeMsec":"1500"}}
+{"replayChatItemAction":{"actions":[{"addChatItemAction":{"item":{"liveChatPaidMessageRenderer":{"message":{"runs":[{"text":"gg"}]},"authorName":{"simpleText":"Bob"},"authorExternalChannelId":"UCbob","purchaseAmountText":{"simpleText":"¥1,000"}}}}}],"videoOffsetTimeMsec":"2000"}}
+{"replayChatItemAction":{"actions":[{"addChatItemAction":{"item":{"liveChatPaidStickerRenderer":{"authorName":{"simpleText":"Alice"},"authorExternalChannelId":"UCalice","purchaseAmountText":{"simpleText":"¥500"}}}}}],"videoOffsetTimeMsec":"2500"}}
+{"replayChatItemAction":{"actions":[{"addChatItemAction":{"item":{"liveChatMembershipItemRenderer":{"headerSubtext":{"runs":[{"text":"Welcome to "},{"text":"Members"}]},"authorName":{"simpleText":"Carol"},"authorExternalChannelId":"UCcarol"}}}}],"videoOffsetTimeMsec":"3000"}}
+{"replayChatItemAction":{"actions":[{"addChatItemAction":{"item":{"liveChatSponsorshipsGiftPurchaseAnnouncementRenderer":{"authorExternalChannelId":"UCdave","timestampUsec":"1700000003000000","header":{"liveChatSponsorshipsHeaderRenderer":{"authorName":{"simpleText":"Dave"},"primaryText":{"runs":[{"text":"Gifted "},{"text":"5"},{"text":" "},{"text":"Members"},{"text":" memberships"}]}}}}}}}],"videoOffsetTimeMsec":"3200"}}
+{"replayChatItemAction":{"actions":[{"addLiveChatTickerItemAction":{}}],"videoOffsetTimeMsec":"3500"}}
+{"replayChatItemAction":{"actions":[{"addChatItem
+"#;
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse_amount("$5.00"), Some((5.0, "$".into())));
+        assert_eq!(parse_amount("¥1,000"), Some((1000.0, "¥".into())));
+        assert_eq!(parse_amount("CA$20.00"), Some((20.0, "CA$".into())));
+        assert_eq!(parse_amount("5.00"), None);
+    }
+
+    #[tokio::test]
+    async fn test_process() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("abc.live_chat.json"), CHAT).unwrap();
+
+        let stats = process(dir.path()).await.unwrap().unwrap();
+        assert_eq!(
+            stats,
+            LiveChatStats {
+                messages: 5,
+                unique_chatters: 4,
+                superchats: 2,
+                superchat_totals: BTreeMap::from([("¥".into(), 1500.0)]),
+                memberships: 1,
+                gifted_memberships: 5,
+                malformed_lines: 1,
+            }
+        );
+
+        // The raw chat stays for the archive site to replay
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("abc.live_chat.json")).unwrap(),
+            CHAT
+        );
+        assert!(!dir.path().join("abc.live_chat.json.gz").exists());
+
+        let mut normalised = String::new();
+        GzDecoder::new(std::fs::File::open(dir.path().join("abc.live_chat.jsonl.gz")).unwrap())
+            .read_to_string(&mut normalised)
+            .unwrap();
+        let first: Value = serde_json::from_str(normalised.lines().next().unwrap()).unwrap();
+        assert_eq!(first["text"], "hello :wave:");
+        assert_eq!(first["time_ms"], 1500);
+        assert!(normalised
+            .lines()
+            .nth(3)
+            .unwrap()
+            .contains("Welcome to Members"));
+        let gift: Value = serde_json::from_str(normalised.lines().nth(4).unwrap()).unwrap();
+        assert_eq!(gift["kind"], "gift_membership");
+        assert_eq!(gift["author"], "Dave");
+        assert_eq!(gift["gifts"], 5);
+
+        let empty = tempfile::tempdir().unwrap();
+        assert!(process(empty.path()).await.unwrap().is_none());
+    }
+}
@@ -1,29 +1,128 @@
+use super::apikeys::{ApiKeyPool, VIDEOS_LIST_COST};
+use super::availability::AvailabilityStatus;
 use super::{format_path, Metadata, MetadataExtractor};
 use anyhow::Context;
 use async_trait::async_trait;
 use reqwest::Client;
 use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
 
 #[derive(Deserialize)]
 struct InfoJson {
     id: String,
-    uploader: String,
-    channel_id: String,
+    /// Name of the extractor, e.g. `Youtube` or `TwitchVod`
+    extractor_key: Option<String>,
+    uploader: Option<String>,
+    uploader_id: Option<String>,
+    channel: Option<String>,
+    channel_id: Option<String>,
     upload_date: String,
     title: String,
+    #[serde(default)]
     description: String,
+    /// Missing while a recorded stream is still live
+    #[serde(default)]
     duration: u64,
-    width: i32,
-    height: i32,
-    fps: f32,
+    width: Option<i32>,
+    height: Option<i32>,
+    fps: Option<f32>,
+    /// Missing when there were no formats to select, e.g. for refresh tasks
+    #[serde(default)]
     format_id: String,
-    view_count: u64,
+    view_count: Option<u64>,
     like_count: Option<i64>,
     dislike_count: Option<i64>,
+    availability: Option<String>,
+    age_limit: Option<u32>,
+    /// Upload time in seconds since the epoch
+    timestamp: Option<i64>,
+    /// Scheduled or actual start of a stream or premiere
+    release_timestamp: Option<i64>,
+    live_status: Option<String>,
+    tags: Option<Vec<String>>,
+    categories: Option<Vec<String>>,
+    chapters: Option<Vec<super::MetadataChapter>>,
+    language: Option<String>,
+    subtitles: Option<BTreeMap<String, Vec<InfoJsonSubtitle>>>,
+    automatic_captions: Option<BTreeMap<String, Vec<InfoJsonSubtitle>>>,
+    thumbnails: Option<Vec<super::MetadataThumbnail>>,
+    #[serde(flatten)]
+    format: super::MetadataFormat,
 }
 
+/// One file format of a subtitle track.
+#[derive(Deserialize)]
+struct InfoJsonSubtitle {
+    name: Option<String>,
+}
+
+impl InfoJson {
+    /// Timestamps as far as yt-dlp knows them, for when the YouTube API is
+    /// not configured or fails.
+    fn timestamps(&self) -> super::MetadataTimestamps {
+        let format = |t: i64| {
+            chrono::DateTime::from_timestamp(t, 0)
+                .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+        };
+        let published_at = self.timestamp.and_then(format).or_else(|| {
+            (self.upload_date.len() == 8)
+                .then(|| format!("{}T00:00:00Z", fix_upload_date(&self.upload_date)))
+        });
+
+        let mut timestamps = super::MetadataTimestamps {
+            published_at,
+            ..Default::default()
+        };
+        match self.live_status.as_deref() {
+            Some("is_upcoming") => {
+                timestamps.scheduled_start_time = self.release_timestamp.and_then(format);
+            }
+            Some("is_live" | "was_live" | "post_live") => {
+                timestamps.actual_start_time = self.release_timestamp.and_then(format);
+                if self.live_status.as_deref() != Some("is_live") && self.duration > 0 {
+                    timestamps.actual_end_time = self
+                        .release_timestamp
+                        .and_then(|t| format(t + self.duration as i64));
+                }
+            }
+            _ => {}
+        }
+        timestamps.with_source(super::TimestampSource::InfoJson)
+    }
+
+    /// Uploaded subtitle tracks, and the automatic captions in the original
+    /// language. Automatic translations into every other language are left
+    /// out, and so is the live chat, which yt-dlp lists as a subtitle.
+    fn subtitle_tracks(&self) -> Vec<super::MetadataSubtitle> {
+        let uploaded = self
+            .subtitles
+            .iter()
+            .flatten()
+            .filter(|(language, _)| *language != "live_chat")
+            .map(|(language, formats)| (language, formats, false));
+        let automatic = self
+            .automatic_captions
+            .iter()
+            .flatten()
+            .filter(|(language, _)| language.ends_with("-orig"))
+            .map(|(language, formats)| (language, formats, true));
+        uploaded
+            .chain(automatic)
+            .map(|(language, formats, automatic)| super::MetadataSubtitle {
+                language: language.clone(),
+                name: formats.iter().find_map(|f| f.name.clone()),
+                automatic,
+            })
+            .collect()
+    }
+}
+
+/// Most videos the YouTube API returns for one request.
+const YOUTUBE_API_BATCH_SIZE: usize = 50;
+
 pub struct YTMetadataExtractor {
-    youtube_api_key: String,
+    api_keys: Arc<ApiKeyPool>,
     youtube_api_url: String,
     client: Client,
     drive_base: String,
@@ -35,6 +134,9 @@ struct YTTSResponse {
 }
 #[derive(Deserialize)]
 struct YTTSItem {
+    #[serde(default)]
+    id: String,
+    status: Option<YTTSItemStatus>,
     #[serde(rename = "liveStreamingDetails")]
     live_streaming_details: Option<YTTSItemLiveStreamingDetails>,
     snippet: Option<YTTSItemSnippet>,
@@ -48,6 +150,36 @@ struct YTTSItemLiveStreamingDetails {
     #[serde(rename = "actualEndTime")]
     actual_end_time: Option<String>,
 }
+#[derive(Deserialize)]
+struct YTTSItemStatus {
+    #[serde(rename = "privacyStatus")]
+    privacy_status: Option<String>,
+}
+#[derive(Deserialize)]
+struct YTErrorResponse {
+    error: YTError,
+}
+#[derive(Deserialize)]
+struct YTError {
+    #[serde(default)]
+    errors: Vec<YTErrorReason>,
+}
+#[derive(Deserialize)]
+struct YTErrorReason {
+    reason: String,
+}
+
+impl YTErrorResponse {
+    fn is_quota_exceeded(body: &str) -> bool {
+        serde_json::from_str::<Self>(body).is_ok_and(|resp| {
+            resp.error
+                .errors
+                .iter()
+                .any(|e| matches!(e.reason.as_str(), "quotaExceeded" | "dailyLimitExceeded"))
+        })
+    }
+}
+
 #[derive(Deserialize)]
 struct YTTSItemSnippet {
     #[serde(rename = "publishedAt")]
@@ -55,45 +187,82 @@ struct YTTSItemSnippet {
 }
 
 impl YTMetadataExtractor {
+    /// Create an extractor that looks videos up with keys from `api_keys`.
+    /// Share the pool between extractors so they count the same quota.
     pub async fn new(
-        youtube_api_key: String,
+        api_keys: Arc<ApiKeyPool>,
         client: Option<Client>,
         drive_base: String,
     ) -> anyhow::Result<Self> {
-        let client = client.unwrap_or_else(|| Client::new());
+        let client = client.unwrap_or_default();
         let youtube_api_url = "https://youtube.googleapis.com".into();
         Ok(Self {
-            youtube_api_key,
+            api_keys,
             youtube_api_url,
             client,
             drive_base,
         })
     }
 
-    async fn get_timestamps(&self, id: &str) -> anyhow::Result<super::MetadataTimestamps> {
-        let url = format!(
-            "{}/youtube/v3/videos?part=snippet%2CliveStreamingDetails&id={}&key={}",
-            self.youtube_api_url, id, self.youtube_api_key,
-        );
-        let resp = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .context("Could not send request")?
-            .error_for_status()
-            .context("Unexpected status code")?
-            .json::<YTTSResponse>()
-            .await
-            .context("Could not parse response")?;
+    /// Look up videos in the YouTube API, up to 50 per request. Videos that
+    /// are private or removed are left out of the response.
+    async fn videos(&self, ids: &[&str], parts: &str) -> anyhow::Result<Vec<YTTSItem>> {
+        let mut items = vec![];
+        for batch in ids.chunks(YOUTUBE_API_BATCH_SIZE) {
+            items.extend(self.videos_batch(batch, parts).await?.items);
+        }
+        Ok(items)
+    }
+
+    /// Request one batch of videos, rotating to the next key whenever a key
+    /// turns out to be out of quota.
+    async fn videos_batch(&self, ids: &[&str], parts: &str) -> anyhow::Result<YTTSResponse> {
+        loop {
+            let key = self.api_keys.acquire(VIDEOS_LIST_COST)?;
+            let url = format!(
+                "{}/youtube/v3/videos?part={}&id={}&key={}",
+                self.youtube_api_url,
+                parts,
+                ids.join("%2C"),
+                key,
+            );
+            // Errors carry the URL, which includes the key
+            let resp = self
+                .client
+                .get(&url)
+                .send()
+                .await
+                .map_err(reqwest::Error::without_url)
+                .context("Could not send request")?;
+            if resp.status() == reqwest::StatusCode::FORBIDDEN {
+                let body = resp.text().await.context("Could not read response")?;
+                if YTErrorResponse::is_quota_exceeded(&body) {
+                    self.api_keys.report_exhausted(&key);
+                    continue;
+                }
+                anyhow::bail!("Unexpected status code 403 Forbidden: {}", body);
+            }
+            return resp
+                .error_for_status()
+                .map_err(reqwest::Error::without_url)
+                .context("Unexpected status code")?
+                .json::<YTTSResponse>()
+                .await
+                .map_err(reqwest::Error::without_url)
+                .context("Could not parse response");
+        }
+    }
 
-        let item = resp
-            .items
+    async fn get_timestamps(&self, id: &str) -> anyhow::Result<super::MetadataTimestamps> {
+        let item = self
+            .videos(&[id], "snippet%2CliveStreamingDetails")
+            .await?
             .into_iter()
             .next()
             .ok_or_else(|| anyhow::anyhow!("No items in response"))?;
 
         Ok(super::MetadataTimestamps {
+            sources: Default::default(),
             published_at: item.snippet.as_ref().and_then(|s| s.published_at.clone()),
             scheduled_start_time: item
                 .live_streaming_details
@@ -107,12 +276,43 @@ impl YTMetadataExtractor {
                 .live_streaming_details
                 .as_ref()
                 .and_then(|d| d.actual_end_time.clone()),
-        })
+        }
+        .with_source(super::TimestampSource::YoutubeApi))
     }
 }
 
 #[async_trait]
 impl MetadataExtractor for YTMetadataExtractor {
+    async fn timestamps(&self, id: &str) -> anyhow::Result<super::MetadataTimestamps> {
+        if self.api_keys.is_empty() {
+            return Ok(Default::default());
+        }
+        self.get_timestamps(id).await
+    }
+
+    async fn availability(
+        &self,
+        ids: &[&str],
+    ) -> anyhow::Result<HashMap<String, AvailabilityStatus>> {
+        // Without the API every video would look removed
+        if self.api_keys.is_empty() {
+            anyhow::bail!("No YouTube API key configured");
+        }
+        let mut statuses: HashMap<_, _> = ids
+            .iter()
+            .map(|id| (id.to_string(), AvailabilityStatus::Unavailable))
+            .collect();
+        for item in self.videos(ids, "status").await? {
+            let status = match item.status.and_then(|s| s.privacy_status).as_deref() {
+                Some("unlisted") => AvailabilityStatus::Unlisted,
+                Some("private") => AvailabilityStatus::Private,
+                _ => AvailabilityStatus::Available,
+            };
+            statuses.insert(item.id, status);
+        }
+        Ok(statuses)
+    }
+
     async fn extract(&self, workdir: &std::path::Path) -> anyhow::Result<Metadata> {
         // Scan all files in the workdir
         let mut files = vec![];
@@ -150,33 +350,137 @@ impl MetadataExtractor for YTMetadataExtractor {
         let info_json: InfoJson =
             serde_json::from_str(&info_json).context("Could not deserialize info.json")?;
 
-        // Get the timestamps
-        let timestamps = self.get_timestamps(&info_json.id).await?;
+        // Get the timestamps. Only YouTube videos are in the YouTube API, and
+        // the info.json fills in what the API does not know.
+        let fallback = info_json.timestamps();
+        let timestamps = match info_json.extractor_key.as_deref() {
+            _ if self.api_keys.is_empty() => fallback,
+            None | Some("Youtube") => match self.get_timestamps(&info_json.id).await {
+                Ok(timestamps) => timestamps.or(fallback),
+                Err(e) => {
+                    warn!("Could not get timestamps from the YouTube API: {:#}", e);
+                    super::metrics::counter_inc("archivebot_timestamp_fallbacks_total", &[]);
+                    fallback
+                }
+            },
+            Some(_) => fallback,
+        };
+
+        let subtitles = info_json.subtitle_tracks();
+        // Thumbnails without a resolution are duplicates in other encodings
+        let thumbnails = info_json
+            .thumbnails
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|t| t.width.is_some())
+            .collect();
 
         // Map the infojson to our metadata
         Ok(Metadata {
             video_id: info_json.id,
-            channel_name: info_json.uploader,
-            channel_id: info_json.channel_id,
+            channel_name: info_json.uploader.or(info_json.channel).unwrap_or_default(),
+            channel_id: info_json
+                .channel_id
+                .or(info_json.uploader_id)
+                .unwrap_or_default(),
             upload_date: fix_upload_date(&info_json.upload_date),
             title: info_json.title,
             description: info_json.description,
             duration: info_json.duration,
-            width: info_json.width,
-            height: info_json.height,
-            fps: info_json.fps,
+            width: info_json.width.unwrap_or(0),
+            height: info_json.height.unwrap_or(0),
+            fps: info_json.fps.unwrap_or(0.0),
             format_id: info_json.format_id,
-            view_count: info_json.view_count,
+            view_count: info_json.view_count.unwrap_or(0),
             like_count: info_json.like_count.unwrap_or(-1),
             dislike_count: info_json.dislike_count.unwrap_or(-1),
             files,
             drive_base: format_path(&self.drive_base),
             archived_timestamp: chrono::Utc::now().to_rfc3339(),
             timestamps: Some(timestamps),
+            restricted_content: restricted_content(
+                info_json.availability.as_deref(),
+                info_json.age_limit,
+            ),
+            tags: info_json.tags.unwrap_or_default(),
+            categories: info_json.categories.unwrap_or_default(),
+            chapters: info_json.chapters.unwrap_or_default(),
+            visibility: info_json.availability,
+            live_status: info_json.live_status,
+            age_limit: info_json.age_limit,
+            language: info_json.language,
+            subtitles,
+            thumbnails,
+            format: (info_json.format != Default::default()).then_some(info_json.format),
+            live_chat: None,
+            live_chat_stats: None,
+            comments: None,
+            storage_path: None,
+            requested_by: None,
+            source_url: None,
+            refreshed_timestamp: None,
+            availability: None,
         })
     }
 }
 
+impl Metadata {
+    /// Take the fields that change after archiving from freshly extracted
+    /// metadata. Returns the names of the fields that changed.
+    pub fn refresh(&mut self, fresh: Metadata) -> Vec<&'static str> {
+        let mut changed = vec![];
+        macro_rules! refresh {
+            ($($field:ident),*) => {
+                $(
+                    if self.$field != fresh.$field {
+                        self.$field = fresh.$field;
+                        changed.push(stringify!($field));
+                    }
+                )*
+            };
+        }
+        refresh!(
+            title,
+            description,
+            channel_name,
+            view_count,
+            like_count,
+            restricted_content,
+            tags,
+            categories,
+            chapters,
+            visibility,
+            live_status,
+            age_limit
+        );
+        // Do not replace timestamps from the API with the info.json fallback
+        let better = match (&self.timestamps, &fresh.timestamps) {
+            (_, None) => false,
+            (Some(stored), Some(fresh)) => fresh.from_api() || !stored.from_api(),
+            (None, Some(_)) => true,
+        };
+        if better && self.timestamps != fresh.timestamps {
+            self.timestamps = fresh.timestamps;
+            changed.push("timestamps");
+        }
+        if !changed.is_empty() {
+            self.refreshed_timestamp = Some(chrono::Utc::now().to_rfc3339());
+        }
+        changed
+    }
+}
+
+/// Map yt-dlp's availability and age limit to the restricted content flag.
+fn restricted_content(availability: Option<&str>, age_limit: Option<u32>) -> Option<String> {
+    match availability {
+        Some("subscriber_only") => Some("members_only".into()),
+        Some("premium_only") => Some("premium_only".into()),
+        Some("needs_auth") if age_limit.unwrap_or(0) < 18 => Some("login_required".into()),
+        _ if age_limit.unwrap_or(0) >= 18 => Some("age_restricted".into()),
+        _ => None,
+    }
+}
+
 /// Convert YYYYMMDD to YYYY-MM-DD
 fn fix_upload_date(date: &str) -> String {
     let mut chars = date.chars();
@@ -188,6 +492,7 @@ fn fix_upload_date(date: &str) -> String {
 
 #[cfg(test)]
 mod tests {
+    use super::super::{MetadataTimestamps, TimestampSource};
     use super::*;
     use mockito::mock;
 
@@ -196,6 +501,143 @@ mod tests {
         assert_eq!(fix_upload_date("19840102"), "1984-01-02");
     }
 
+    #[test]
+    fn test_refresh() {
+        let mut stored = Metadata {
+            title: "Old title".into(),
+            view_count: 100,
+            like_count: 10,
+            files: vec![super::super::MetadataFileEntry {
+                name: "abc.mp4".into(),
+                size: 1,
+            }],
+            ..Default::default()
+        };
+        let fresh = || Metadata {
+            title: "New title".into(),
+            view_count: 200,
+            like_count: 10,
+            ..Default::default()
+        };
+        assert_eq!(stored.refresh(fresh()), ["title", "view_count"]);
+        assert_eq!(stored.title, "New title");
+        assert_eq!(stored.files.len(), 1);
+        assert!(stored.refreshed_timestamp.is_some());
+        assert!(stored.refresh(fresh()).is_empty());
+    }
+
+    #[test]
+    fn test_info_json_timestamps() {
+        let info_json: InfoJson = serde_json::from_str(
+            r#"{"id":"abc","upload_date":"20240105","title":"t","duration":3600,
+                "live_status":"was_live","release_timestamp":1704445200}"#,
+        )
+        .unwrap();
+        let timestamps = info_json.timestamps();
+        assert_eq!(
+            timestamps.published_at.as_deref(),
+            Some("2024-01-05T00:00:00Z")
+        );
+        assert_eq!(
+            timestamps.actual_start_time.as_deref(),
+            Some("2024-01-05T09:00:00Z")
+        );
+        assert_eq!(
+            timestamps.actual_end_time.as_deref(),
+            Some("2024-01-05T10:00:00Z")
+        );
+        assert_eq!(timestamps.scheduled_start_time, None);
+        assert!(!timestamps.from_api());
+
+        // The API takes precedence, the info.json fills the gaps
+        let merged = MetadataTimestamps {
+            published_at: Some("2024-01-04T12:00:00Z".into()),
+            ..Default::default()
+        }
+        .with_source(TimestampSource::YoutubeApi)
+        .or(timestamps);
+        assert_eq!(merged.published_at.as_deref(), Some("2024-01-04T12:00:00Z"));
+        assert_eq!(merged.sources["publishedAt"], TimestampSource::YoutubeApi);
+        assert_eq!(merged.sources["actualStartTime"], TimestampSource::InfoJson);
+        assert!(!merged.sources.contains_key("scheduledStartTime"));
+        assert!(merged.from_api());
+    }
+
+    #[tokio::test]
+    async fn test_extract() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("abc.info.json"),
+            r#"{"id":"abc","extractor_key":"Youtube","channel":"Channel","channel_id":"UCabc",
+                "upload_date":"20240105","title":"Title","duration":600,"format_id":"137+140",
+                "availability":"unlisted","live_status":"not_live","age_limit":0,"language":"en",
+                "tags":["a","b"],"categories":["Music"],
+                "chapters":[{"start_time":0.0,"end_time":60.0,"title":"Intro"}],
+                "subtitles":{"de":[{"ext":"vtt","name":"German"}],"live_chat":[{"ext":"json"}]},
+                "automatic_captions":{"en-orig":[{"ext":"vtt","name":"English (Original)"}],
+                    "fr":[{"ext":"vtt"}]},
+                "thumbnails":[{"url":"https://i.ytimg.com/vi/abc/hq.jpg","width":480,"height":360},
+                    {"url":"https://i.ytimg.com/vi_webp/abc/hq.webp"}],
+                "format":"137 - 1920x1080 (1080p)+140 - audio only","ext":"mp4",
+                "vcodec":"avc1.640028","acodec":"mp4a.40.2","tbr":4500.5,"asr":44100}"#,
+        )
+        .unwrap();
+        let extractor = YTMetadataExtractor::new(
+            Arc::new(ApiKeyPool::parse("", 100)),
+            None,
+            "drive".to_string(),
+        )
+        .await
+        .unwrap();
+
+        let metadata = extractor.extract(dir.path()).await.unwrap();
+        assert_eq!(metadata.tags, ["a", "b"]);
+        assert_eq!(metadata.categories, ["Music"]);
+        assert_eq!(metadata.chapters[0].title, "Intro");
+        assert_eq!(metadata.visibility.as_deref(), Some("unlisted"));
+        assert_eq!(metadata.live_status.as_deref(), Some("not_live"));
+        assert_eq!(metadata.language.as_deref(), Some("en"));
+        assert_eq!(
+            metadata.subtitles,
+            [
+                super::super::MetadataSubtitle {
+                    language: "de".into(),
+                    name: Some("German".into()),
+                    automatic: false,
+                },
+                super::super::MetadataSubtitle {
+                    language: "en-orig".into(),
+                    name: Some("English (Original)".into()),
+                    automatic: true,
+                },
+            ]
+        );
+        assert_eq!(metadata.thumbnails.len(), 1);
+        let format = metadata.format.unwrap();
+        assert_eq!(format.vcodec.as_deref(), Some("avc1.640028"));
+        assert_eq!(format.tbr, Some(4500.5));
+        assert_eq!(format.asr, Some(44100));
+        assert_eq!(format.dynamic_range, None);
+    }
+
+    #[test]
+    fn test_restricted_content() {
+        assert_eq!(restricted_content(Some("public"), Some(0)), None);
+        assert_eq!(restricted_content(None, None), None);
+        assert_eq!(
+            restricted_content(Some("subscriber_only"), Some(0)).as_deref(),
+            Some("members_only")
+        );
+        assert_eq!(
+            restricted_content(Some("needs_auth"), Some(18)).as_deref(),
+            Some("age_restricted")
+        );
+        assert_eq!(
+            restricted_content(Some("needs_auth"), None).as_deref(),
+            Some("login_required")
+        );
+    }
+
     fn get_mock_yt(api_key: &str, video_id: &str) -> mockito::Mock {
         mock("GET", "/youtube/v3/videos")
             .match_query(mockito::Matcher::UrlEncoded("key".into(), api_key.into()))
@@ -274,9 +716,13 @@ mod tests {
         let video_id = "test-video-id";
 
         let _m = get_mock_yt(api_key, video_id);
-        let mut extractor = YTMetadataExtractor::new("asdf".to_string(), None, "drive".to_string())
-            .await
-            .unwrap();
+        let mut extractor = YTMetadataExtractor::new(
+            Arc::new(ApiKeyPool::parse("asdf", 100)),
+            None,
+            "drive".to_string(),
+        )
+        .await
+        .unwrap();
 
         // Override the URL
         extractor.youtube_api_url = mockito::server_url();
@@ -296,4 +742,78 @@ mod tests {
             "3333-01-01T00:00:00Z"
         );
     }
+
+    #[tokio::test]
+    async fn test_quota_rotation() {
+        let exhausted = mock("GET", "/youtube/v3/videos")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("id".into(), "rotate1".into()),
+                mockito::Matcher::UrlEncoded("key".into(), "exhausted-key".into()),
+            ]))
+            .with_status(403)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"error":{"code":403,"errors":[{"reason":"quotaExceeded"}]}}"#)
+            .expect(1)
+            .create();
+        let working = mock("GET", "/youtube/v3/videos")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("id".into(), "rotate1".into()),
+                mockito::Matcher::UrlEncoded("key".into(), "working-key".into()),
+            ]))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"items":[{"id":"rotate1","status":{"privacyStatus":"public"}}]}"#)
+            .expect(2)
+            .create();
+        let mut extractor = YTMetadataExtractor::new(
+            Arc::new(ApiKeyPool::parse("exhausted-key,working-key", 100)),
+            None,
+            "drive".to_string(),
+        )
+        .await
+        .unwrap();
+        extractor.youtube_api_url = mockito::server_url();
+
+        // The exhausted key is only tried once
+        for _ in 0..2 {
+            let statuses = extractor.availability(&["rotate1"]).await.unwrap();
+            assert_eq!(statuses["rotate1"], AvailabilityStatus::Available);
+        }
+        exhausted.assert();
+        working.assert();
+    }
+
+    #[tokio::test]
+    async fn test_availability() {
+        let _m = mock("GET", "/youtube/v3/videos")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("part".into(), "status".into()),
+                mockito::Matcher::UrlEncoded("id".into(), "public1,unlisted1,removed1".into()),
+            ]))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"items":[
+                    {"id":"public1","status":{"privacyStatus":"public"}},
+                    {"id":"unlisted1","status":{"privacyStatus":"unlisted"}}
+                ]}"#,
+            )
+            .create();
+        let mut extractor = YTMetadataExtractor::new(
+            Arc::new(ApiKeyPool::parse("asdf", 100)),
+            None,
+            "drive".to_string(),
+        )
+        .await
+        .unwrap();
+        extractor.youtube_api_url = mockito::server_url();
+
+        let statuses = extractor
+            .availability(&["public1", "unlisted1", "removed1"])
+            .await
+            .unwrap();
+        assert_eq!(statuses["public1"], AvailabilityStatus::Available);
+        assert_eq!(statuses["unlisted1"], AvailabilityStatus::Unlisted);
+        assert_eq!(statuses["removed1"], AvailabilityStatus::Unavailable);
+    }
 }
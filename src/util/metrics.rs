@@ -1,8 +1,10 @@
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Body, Request, Response, Server};
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
 use std::convert::Infallible;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::RwLock;
 
@@ -10,6 +12,46 @@ use crate::archiver::{ArchiverState, ARCHIVER_STATES};
 
 use super::{dir_size, get_cache_dir};
 
+lazy_static! {
+    /// Counters and gauges reported by other modules, keyed by the series
+    /// name including its labels.
+    static ref SERIES: Mutex<BTreeMap<String, f64>> = Mutex::new(BTreeMap::new());
+}
+
+fn series_key(name: &str, labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return name.to_string();
+    }
+    let labels = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, v.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{}{{{}}}", name, labels)
+}
+
+/// Increment a counter by one.
+pub fn counter_inc(name: &str, labels: &[(&str, &str)]) {
+    counter_add(name, labels, 1.0);
+}
+
+/// Add the given value to a counter.
+pub fn counter_add(name: &str, labels: &[(&str, &str)], value: f64) {
+    *SERIES
+        .lock()
+        .unwrap()
+        .entry(series_key(name, labels))
+        .or_insert(0.0) += value;
+}
+
+/// Set a gauge to the given value.
+pub fn gauge_set(name: &str, labels: &[(&str, &str)], value: f64) {
+    SERIES
+        .lock()
+        .unwrap()
+        .insert(series_key(name, labels), value);
+}
+
 async fn generate_metrics(state: Arc<RwLock<ArchiverState>>) -> String {
     let state = state.read().await;
     let state_metrics = ARCHIVER_STATES
@@ -34,7 +76,14 @@ async fn generate_metrics(state: Arc<RwLock<ArchiverState>>) -> String {
         }
     );
 
-    format!("{}{}", state_metrics, cache_dir_metrics)
+    let series_metrics = SERIES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(key, value)| format!("{} {}\n", key, value))
+        .collect::<String>();
+
+    format!("{}{}{}", state_metrics, cache_dir_metrics, series_metrics)
 }
 
 pub async fn serve_metrics_endpoint(
@@ -70,3 +119,20 @@ pub async fn serve_metrics_endpoint(
 
     tokio::join!(server, rx_listener).0
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_generate_metrics() {
+        counter_inc("archivebot_test_total", &[("reason", "a")]);
+        counter_inc("archivebot_test_total", &[("reason", "a")]);
+        gauge_set("archivebot_test_gauge", &[], 1.5);
+
+        let metrics = generate_metrics(Arc::new(RwLock::new(ArchiverState::Idle))).await;
+        assert!(metrics.contains("archivebot_state{state=\"Idle\"} 1\n"));
+        assert!(metrics.contains("archivebot_test_total{reason=\"a\"} 2\n"));
+        assert!(metrics.contains("archivebot_test_gauge 1.5\n"));
+    }
+}
@@ -1,14 +1,33 @@
 use anyhow::Context;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
+pub mod apikeys;
 pub mod archive;
+pub mod artifacts;
+pub mod availability;
+pub mod breaker;
+pub mod comments;
+pub mod cookies;
+pub mod ffprobe;
 pub mod github;
+pub mod livechat;
 pub mod metadata;
 pub mod metrics;
+pub mod playlist;
+pub mod pot;
+pub mod probe;
+pub mod profile;
+pub mod proxy;
 pub mod rclone;
+pub mod schedule;
+pub mod source;
+pub mod task;
 pub mod tasq;
+pub mod tools;
+pub mod watcher;
 pub mod ytdl;
 
 pub async fn get_cache_dir() -> anyhow::Result<PathBuf> {
@@ -45,6 +64,18 @@ pub fn dir_size(path: &Path) -> anyhow::Result<u64> {
     Ok(size)
 }
 
+/// Create a file that everything written to gets gzipped.
+pub fn gzip_writer(
+    path: &Path,
+) -> anyhow::Result<flate2::write::GzEncoder<std::io::BufWriter<std::fs::File>>> {
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("Could not create {}", path.display()))?;
+    Ok(flate2::write::GzEncoder::new(
+        std::io::BufWriter::new(file),
+        flate2::Compression::default(),
+    ))
+}
+
 pub async fn tempfile() -> anyhow::Result<std::fs::File> {
     tempfile::tempfile_in(
         get_cache_dir()
@@ -89,11 +120,204 @@ pub trait TaskQueue {
 
 pub struct VideoDownloadResult {
     pub output: std::process::Output,
+    /// Why the download failed, if it did.
+    pub failure: Option<DownloadFailure>,
+}
+
+/// Why a download failed, as far as the downloader can tell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadFailure {
+    Private,
+    Removed,
+    Copyright,
+    MembersOnly,
+    AgeRestricted,
+    GeoBlocked,
+    /// Premiere or live stream that has not started yet
+    Upcoming,
+    /// Live stream that is still running
+    Live,
+    /// "Sign in to confirm you're not a bot"
+    BotCheck,
+    LoginRequired,
+    /// The cookies used for the download have expired or been rotated
+    CookiesExpired,
+    /// HTTP 429
+    RateLimited,
+    /// The PO token provider could not be used
+    PotFailure,
+    /// The extractor is broken, usually fixed by updating the downloader
+    Extractor,
+    /// The download finished but the media is truncated or corrupt
+    InvalidMedia,
+    /// The video is outside the configured size or duration limits, the
+    /// task asks for a profile or cookie jar that does not exist, or there is
+    /// no URL to download a generic video from
+    Rejected,
+    Unknown,
+}
+
+impl DownloadFailure {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DownloadFailure::Private => "private",
+            DownloadFailure::Removed => "removed",
+            DownloadFailure::Copyright => "copyright",
+            DownloadFailure::MembersOnly => "members_only",
+            DownloadFailure::AgeRestricted => "age_restricted",
+            DownloadFailure::GeoBlocked => "geo_blocked",
+            DownloadFailure::Upcoming => "upcoming",
+            DownloadFailure::Live => "live",
+            DownloadFailure::BotCheck => "bot_check",
+            DownloadFailure::LoginRequired => "login_required",
+            DownloadFailure::CookiesExpired => "cookies_expired",
+            DownloadFailure::RateLimited => "rate_limited",
+            DownloadFailure::PotFailure => "pot_failure",
+            DownloadFailure::Extractor => "extractor",
+            DownloadFailure::InvalidMedia => "invalid_media",
+            DownloadFailure::Rejected => "rejected",
+            DownloadFailure::Unknown => "unknown",
+        }
+    }
+
+    /// Whether retrying the same video later cannot help.
+    pub fn is_permanent(&self) -> bool {
+        matches!(
+            self,
+            DownloadFailure::Private
+                | DownloadFailure::Removed
+                | DownloadFailure::Copyright
+                | DownloadFailure::Rejected
+        )
+    }
+}
+
+impl std::fmt::Display for DownloadFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::error::Error for DownloadFailure {}
+
+/// Per-task settings for a download.
+#[derive(Debug, Default, Clone)]
+pub struct DownloadOptions {
+    /// Download profile to use. If unset, the downloader picks one based on
+    /// the channel.
+    pub profile: Option<String>,
+    /// Cookie jar to use. If unset, the downloader picks one based on the
+    /// channel, if any.
+    pub cookies: Option<String>,
+    /// Record a live or upcoming stream from its start instead of skipping it.
+    pub live: bool,
+    /// What the downloads of the task share, from `VideoDownloader::prepare`.
+    /// Each download picks its own if unset.
+    pub session: Option<DownloadSession>,
+}
+
+/// Route, PO token server, profile and cookie jar picked once for all the
+/// downloads of a task, so they go through the same proxy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadSession {
+    pub route: Option<proxy::Route>,
+    pub pot: url::Url,
+    pub profile: String,
+    pub cookies: Option<String>,
+}
+
+/// Whether the live chat replay of a video was archived.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LiveChatStatus {
+    Present,
+    /// The video has no live chat replay
+    Unavailable,
+    /// The live chat could not be downloaded, even after retrying
+    Failed,
 }
 
 #[async_trait]
-pub trait VideoDownloader {
-    async fn download(&self, url: &str, workdir: &Path) -> anyhow::Result<VideoDownloadResult>;
+pub trait VideoDownloader: Send + Sync {
+    /// Pick what all the downloads of a task share and add it to the
+    /// options, so later calls do not pick again.
+    async fn prepare(
+        &self,
+        _url: &str,
+        options: &DownloadOptions,
+    ) -> anyhow::Result<DownloadOptions> {
+        Ok(options.clone())
+    }
+
+    /// Look up a video without downloading it. Videos that cannot be
+    /// downloaded at all fail with a `DownloadFailure`.
+    async fn probe(
+        &self,
+        _url: &str,
+        _options: &DownloadOptions,
+    ) -> anyhow::Result<probe::VideoProbe> {
+        Ok(probe::VideoProbe::default())
+    }
+
+    async fn download(
+        &self,
+        url: &str,
+        workdir: &Path,
+        options: &DownloadOptions,
+    ) -> anyhow::Result<VideoDownloadResult>;
+
+    /// Download the live chat replay of a video into `workdir`, retrying
+    /// failures. Downloaders without live chat support report it unavailable.
+    /// Errors carry the `DownloadFailure` when it is known.
+    async fn download_live_chat(
+        &self,
+        _url: &str,
+        _workdir: &Path,
+        _options: &DownloadOptions,
+    ) -> anyhow::Result<LiveChatStatus> {
+        Ok(LiveChatStatus::Unavailable)
+    }
+
+    /// Fetch the comments of a video into a separate artifact in `workdir`,
+    /// skipping the ones already in `archived` if given. Returns `None` if
+    /// comments are disabled for the video. Errors carry the
+    /// `DownloadFailure` when it is known.
+    async fn download_comments(
+        &self,
+        _url: &str,
+        _workdir: &Path,
+        _options: &DownloadOptions,
+        _archived: Option<&comments::CommentsInfo>,
+    ) -> anyhow::Result<Option<comments::CommentsInfo>> {
+        Ok(None)
+    }
+
+    /// Write only the info.json of a video to `workdir`, without downloading
+    /// any media.
+    async fn download_info(
+        &self,
+        _url: &str,
+        _workdir: &Path,
+        _options: &DownloadOptions,
+    ) -> anyhow::Result<()> {
+        anyhow::bail!("Downloading metadata only is not supported")
+    }
+
+    /// List the entries of a channel, playlist or channel tab without
+    /// extracting each video.
+    async fn list_entries(
+        &self,
+        _url: &str,
+        _options: &DownloadOptions,
+    ) -> anyhow::Result<Vec<playlist::PlaylistEntry>> {
+        anyhow::bail!("Listing playlists is not supported")
+    }
+
+    /// Housekeeping that must not overlap with a download, such as updating
+    /// the downloader itself. Called between tasks.
+    async fn maintain(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -107,7 +331,8 @@ pub trait SelfInstallable {
     async fn install(&self) -> anyhow::Result<()>;
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
 pub struct Metadata {
     pub video_id: String,
     pub channel_name: String,
@@ -127,15 +352,119 @@ pub struct Metadata {
     pub drive_base: String,
     pub archived_timestamp: String,
     pub timestamps: Option<MetadataTimestamps>,
+    /// Set if the video is not publicly watchable, e.g. `members_only` or
+    /// `age_restricted`, so the archive site can decide how to show it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restricted_content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub live_chat: Option<LiveChatStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub live_chat_stats: Option<livechat::LiveChatStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comments: Option<comments::CommentsInfo>,
+    /// Directory the files are stored in, if not the video ID
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requested_by: Option<String>,
+    /// URL a generic video was downloaded from, as its ID does not lead back
+    /// to it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_url: Option<String>,
+    /// When the metadata was last refreshed after archiving
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refreshed_timestamp: Option<String>,
+    /// Whether the video is still available upstream
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub availability: Option<availability::AvailabilityInfo>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub chapters: Vec<MetadataChapter>,
+    /// yt-dlp's availability at the time of archiving, e.g. `public` or
+    /// `unlisted`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visibility: Option<String>,
+    /// e.g. `not_live`, `was_live` or `post_live`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub live_status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub age_limit: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Subtitle tracks available upstream
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub subtitles: Vec<MetadataSubtitle>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub thumbnails: Vec<MetadataThumbnail>,
+    /// Details of the downloaded format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<MetadataFormat>,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct MetadataFileEntry {
     pub name: String,
     pub size: u64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MetadataChapter {
+    pub start_time: f64,
+    pub end_time: f64,
+    #[serde(default)]
+    pub title: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MetadataSubtitle {
+    pub language: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Generated by YouTube rather than uploaded
+    pub automatic: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MetadataThumbnail {
+    pub url: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+/// Format fields as yt-dlp names them in the info.json.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct MetadataFormat {
+    /// Description of the format, e.g. `137 - 1920x1080 (1080p)+140 - audio only`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ext: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vcodec: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acodec: Option<String>,
+    /// e.g. `SDR` or `HDR10`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dynamic_range: Option<String>,
+    /// Total bitrate in kbit/s
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tbr: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vbr: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub abr: Option<f64>,
+    /// Audio sampling rate in Hz
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asr: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio_channels: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
 pub struct MetadataTimestamps {
     #[serde(rename = "actualStartTime")]
     pub actual_start_time: Option<String>,
@@ -145,17 +474,103 @@ pub struct MetadataTimestamps {
     pub scheduled_start_time: Option<String>,
     #[serde(rename = "actualEndTime")]
     pub actual_end_time: Option<String>,
+    /// Where each timestamp came from, by field name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sources: BTreeMap<String, TimestampSource>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampSource {
+    YoutubeApi,
+    InfoJson,
+}
+
+impl MetadataTimestamps {
+    fn fields(&mut self) -> [(&'static str, &mut Option<String>); 4] {
+        [
+            ("actualStartTime", &mut self.actual_start_time),
+            ("publishedAt", &mut self.published_at),
+            ("scheduledStartTime", &mut self.scheduled_start_time),
+            ("actualEndTime", &mut self.actual_end_time),
+        ]
+    }
+
+    /// Record `source` as the source of every timestamp that is set.
+    pub fn with_source(mut self, source: TimestampSource) -> Self {
+        let names: Vec<_> = self
+            .fields()
+            .into_iter()
+            .filter(|(_, value)| value.is_some())
+            .map(|(name, _)| name)
+            .collect();
+        for name in names {
+            self.sources.insert(name.into(), source);
+        }
+        self
+    }
+
+    /// Fill the timestamps that are not set from `fallback`.
+    pub fn or(mut self, mut fallback: Self) -> Self {
+        let mut sources = std::mem::take(&mut self.sources);
+        let fallback_sources = std::mem::take(&mut fallback.sources);
+        for ((name, value), (_, fallback_value)) in self.fields().into_iter().zip(fallback.fields())
+        {
+            if value.is_none() && fallback_value.is_some() {
+                *value = fallback_value.take();
+                if let Some(source) = fallback_sources.get(name) {
+                    sources.insert(name.into(), *source);
+                }
+            }
+        }
+        self.sources = sources;
+        self
+    }
+
+    /// Whether any timestamp came from the YouTube API.
+    pub fn from_api(&self) -> bool {
+        self.sources
+            .values()
+            .any(|source| *source == TimestampSource::YoutubeApi)
+    }
 }
 
 #[async_trait]
-pub trait ArchiveSite {
+pub trait ArchiveSite: Send + Sync {
     async fn is_archived(&self, id: &str) -> anyhow::Result<bool>;
+    /// Get the metadata of an archived video, or `None` if it is not archived.
+    async fn get(&self, id: &str) -> anyhow::Result<Option<Metadata>>;
     async fn archive(&self, id: &str, metadata: &Metadata) -> anyhow::Result<()>;
+
+    /// Get the metadata of up to `count` randomly picked archived videos.
+    async fn sample(&self, _count: usize) -> anyhow::Result<Vec<Metadata>> {
+        Ok(vec![])
+    }
 }
 
 #[async_trait]
-pub trait MetadataExtractor {
+pub trait MetadataExtractor: Send + Sync {
     async fn extract(&self, workdir: &Path) -> anyhow::Result<Metadata>;
+
+    /// Look up whether videos are still available upstream.
+    async fn availability(
+        &self,
+        _ids: &[&str],
+    ) -> anyhow::Result<std::collections::HashMap<String, availability::AvailabilityStatus>> {
+        anyhow::bail!("Looking up availability is not supported")
+    }
+
+    /// Look up the publishing and streaming times of a video.
+    async fn timestamps(&self, _id: &str) -> anyhow::Result<MetadataTimestamps> {
+        Ok(MetadataTimestamps::default())
+    }
+}
+
+#[async_trait]
+pub trait MediaValidator {
+    /// Make sure the downloaded media in `workdir` is complete and matches
+    /// its metadata. Broken media fails with `DownloadFailure::InvalidMedia`.
+    async fn validate(&self, workdir: &Path, metadata: &Metadata) -> anyhow::Result<()>;
 }
 
 pub fn format_path(path: &str) -> String {
@@ -0,0 +1,163 @@
+use anyhow::Context;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// What kind of upload a playlist entry is.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentType {
+    Video,
+    /// Live stream or premiere, past or upcoming
+    Stream,
+    Short,
+}
+
+/// One entry of a flat playlist listing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistEntry {
+    pub id: String,
+    pub url: Option<String>,
+    /// The entry is itself a playlist, such as a tab of a channel
+    pub is_playlist: bool,
+    pub content: ContentType,
+    /// Upload or stream date, if the listing includes it
+    pub date: Option<NaiveDate>,
+}
+
+/// Which entries of a playlist to archive.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct PlaylistFilter {
+    /// Only entries from this day on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<NaiveDate>,
+    /// Only entries up to and including this day
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<NaiveDate>,
+    /// Only entries of these kinds, all if empty
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub content: Vec<ContentType>,
+}
+
+impl PlaylistFilter {
+    /// Whether an entry passes the filter. Entries without a date pass date
+    /// ranges, since flat listings do not always include one.
+    pub fn matches(&self, entry: &PlaylistEntry) -> bool {
+        if !self.content.is_empty() && !self.content.contains(&entry.content) {
+            return false;
+        }
+        match entry.date {
+            Some(date) => {
+                self.after.is_none_or(|after| date >= after)
+                    && self.before.is_none_or(|before| date <= before)
+            }
+            None => true,
+        }
+    }
+}
+
+fn parse_entry(entry: &Value, entries: &mut Vec<PlaylistEntry>) {
+    // Playlists that were extracted in full list their entries inline
+    if let Some(nested) = entry["entries"].as_array() {
+        for nested in nested {
+            parse_entry(nested, entries);
+        }
+        return;
+    }
+    let id = match entry["id"].as_str() {
+        Some(id) => id.to_string(),
+        None => return,
+    };
+    let url = entry["url"].as_str().map(String::from);
+
+    let is_playlist = entry["_type"] == "playlist"
+        || entry["ie_key"]
+            .as_str()
+            .is_some_and(|key| key.ends_with("Tab") || key.ends_with("Playlist"));
+    let content = if url.as_deref().is_some_and(|u| u.contains("/shorts/")) {
+        ContentType::Short
+    } else if matches!(
+        entry["live_status"].as_str(),
+        Some("is_upcoming" | "is_live" | "was_live" | "post_live")
+    ) {
+        ContentType::Stream
+    } else {
+        ContentType::Video
+    };
+    let date = entry["upload_date"]
+        .as_str()
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y%m%d").ok())
+        .or_else(|| {
+            entry["release_timestamp"]
+                .as_i64()
+                .or(entry["timestamp"].as_i64())
+                .and_then(|t| chrono::DateTime::from_timestamp(t, 0))
+                .map(|t| t.date_naive())
+        });
+
+    entries.push(PlaylistEntry {
+        id,
+        url,
+        is_playlist,
+        content,
+        date,
+    });
+}
+
+/// Parse the output of `yt-dlp --flat-playlist --dump-single-json`.
+pub fn parse(json: &[u8]) -> anyhow::Result<Vec<PlaylistEntry>> {
+    let playlist: Value = serde_json::from_slice(json).context("Could not parse playlist")?;
+    let mut entries = vec![];
+    match playlist["entries"].as_array() {
+        Some(_) => parse_entry(&playlist, &mut entries),
+        None => anyhow::bail!("Not a playlist"),
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static PLAYLIST: &str = r#"{"_type":"playlist","id":"UCabc","entries":[
+        {"_type":"url","ie_key":"Youtube","id":"aaaaaaaaaaa","url":"https://www.youtube.com/watch?v=aaaaaaaaaaa","upload_date":"20240105"},
+        {"_type":"url","ie_key":"Youtube","id":"bbbbbbbbbbb","url":"https://www.youtube.com/watch?v=bbbbbbbbbbb","live_status":"was_live","timestamp":1700000000},
+        {"_type":"url","ie_key":"Youtube","id":"ccccccccccc","url":"https://www.youtube.com/shorts/ccccccccccc"},
+        {"_type":"url","ie_key":"YoutubeTab","id":"UCabc","url":"https://www.youtube.com/@abc/streams"},
+        {"_type":"playlist","id":"UCabc-members","entries":[{"id":"ddddddddddd"}]}
+    ]}"#;
+
+    #[test]
+    fn test_parse() {
+        let entries = parse(PLAYLIST.as_bytes()).unwrap();
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries[0].content, ContentType::Video);
+        assert_eq!(entries[0].date, NaiveDate::from_ymd_opt(2024, 1, 5));
+        assert_eq!(entries[1].content, ContentType::Stream);
+        assert_eq!(entries[1].date, NaiveDate::from_ymd_opt(2023, 11, 14));
+        assert_eq!(entries[2].content, ContentType::Short);
+        assert!(entries[3].is_playlist);
+        assert_eq!(entries[4].id, "ddddddddddd");
+        assert!(!entries[4].is_playlist);
+
+        assert!(parse(br#"{"id":"aaaaaaaaaaa"}"#).is_err());
+    }
+
+    #[test]
+    fn test_filter() {
+        let entries = parse(PLAYLIST.as_bytes()).unwrap();
+        let filter: PlaylistFilter =
+            serde_json::from_str(r#"{"after":"2024-01-01","content":["video","short"]}"#).unwrap();
+        let matching: Vec<_> = entries
+            .iter()
+            .filter(|e| filter.matches(e))
+            .map(|e| e.id.as_str())
+            .collect();
+        assert_eq!(
+            matching,
+            ["aaaaaaaaaaa", "ccccccccccc", "UCabc", "ddddddddddd"]
+        );
+        assert!(PlaylistFilter::default().matches(&entries[1]));
+    }
+}
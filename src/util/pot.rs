@@ -0,0 +1,206 @@
+use super::metrics;
+use super::DownloadFailure;
+use anyhow::Context;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+struct Provider {
+    url: url::Url,
+    healthy: AtomicBool,
+}
+
+/// PO token providers (bgutil HTTP servers) that yt-dlp gets its tokens from.
+/// Downloads use the first healthy provider, and providers are health checked
+/// periodically so a broken one is skipped before it fails downloads.
+pub struct PotProviders {
+    providers: Vec<Provider>,
+    check_interval: Duration,
+    last_check: Mutex<Option<Instant>>,
+    client: reqwest::Client,
+}
+
+impl PotProviders {
+    /// Create the providers from a comma-separated list of base URLs, in
+    /// order of preference. All providers start out healthy.
+    pub fn parse(urls: &str, check_interval: Duration) -> anyhow::Result<Self> {
+        let providers = urls
+            .split(',')
+            .map(str::trim)
+            .filter(|u| !u.is_empty())
+            .map(|u| {
+                Ok(Provider {
+                    url: url::Url::parse(u)
+                        .with_context(|| format!("Could not parse POT server URL {}", u))?,
+                    healthy: AtomicBool::new(true),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if providers.is_empty() {
+            anyhow::bail!("No POT server URL configured");
+        }
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .context("Could not create HTTP client")?;
+        let pot = Self {
+            providers,
+            check_interval,
+            last_check: Mutex::new(None),
+            client,
+        };
+        for provider in &pot.providers {
+            pot.set_healthy(provider, true);
+        }
+        Ok(pot)
+    }
+
+    /// The yt-dlp arguments that point the POT plugin at `provider`. Every
+    /// yt-dlp call must use this so they cannot diverge.
+    pub fn args(provider: &url::Url) -> Vec<String> {
+        vec![
+            "--extractor-args".into(),
+            format!(
+                "youtubepot-bgutilhttp:base_url={}",
+                provider.as_str().trim_end_matches('/')
+            ),
+        ]
+    }
+
+    fn label(url: &url::Url) -> String {
+        url.origin().ascii_serialization()
+    }
+
+    fn set_healthy(&self, provider: &Provider, healthy: bool) {
+        let was_healthy = provider.healthy.swap(healthy, Ordering::SeqCst);
+        let label = Self::label(&provider.url);
+        if was_healthy && !healthy {
+            warn!("POT server {} is unhealthy", label);
+        } else if !was_healthy && healthy {
+            info!("POT server {} is healthy again", label);
+        }
+        metrics::gauge_set(
+            "archivebot_pot_provider_healthy",
+            &[("provider", &label)],
+            if healthy { 1.0 } else { 0.0 },
+        );
+    }
+
+    /// Check whether a provider answers its ping endpoint.
+    async fn ping(&self, url: &url::Url) -> anyhow::Result<()> {
+        let ping = url.join("ping").context("Could not build ping URL")?;
+        self.client
+            .get(ping)
+            .send()
+            .await
+            .context("Could not reach POT server")?
+            .error_for_status()
+            .context("POT server returned an error")?;
+        Ok(())
+    }
+
+    /// Health check every provider.
+    pub async fn check(&self) {
+        *self.last_check.lock().unwrap() = Some(Instant::now());
+        let results =
+            futures_util::future::join_all(self.providers.iter().map(|p| self.ping(&p.url))).await;
+        for (provider, res) in self.providers.iter().zip(results) {
+            if let Err(e) = &res {
+                debug!("POT server {} failed health check: {:#}", provider.url, e);
+            }
+            self.set_healthy(provider, res.is_ok());
+        }
+    }
+
+    /// Health check the providers if the check interval has passed.
+    pub async fn maintain(&self) {
+        let due = self
+            .last_check
+            .lock()
+            .unwrap()
+            .is_none_or(|last| last.elapsed() >= self.check_interval);
+        if due {
+            self.check().await;
+        }
+    }
+
+    /// Pick the provider to use for a download. If no provider is known to be
+    /// healthy, all of them are checked again first.
+    pub async fn acquire(&self) -> anyhow::Result<url::Url> {
+        if let Some(provider) = self.healthy() {
+            return Ok(provider);
+        }
+        self.check().await;
+        self.healthy()
+            .ok_or_else(|| anyhow::anyhow!("No healthy POT server available"))
+    }
+
+    fn healthy(&self) -> Option<url::Url> {
+        self.providers
+            .iter()
+            .find(|p| p.healthy.load(Ordering::SeqCst))
+            .map(|p| p.url.clone())
+    }
+
+    /// Record the outcome of a download. A PO token failure marks the provider
+    /// unhealthy so the next download fails over to another one.
+    pub fn report(&self, url: &url::Url, failure: Option<DownloadFailure>) {
+        if failure != Some(DownloadFailure::PotFailure) {
+            return;
+        }
+
+        let label = Self::label(url);
+        metrics::counter_inc("archivebot_pot_failures_total", &[("provider", &label)]);
+        if let Some(provider) = self.providers.iter().find(|p| &p.url == url) {
+            self.set_healthy(provider, false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mockito::mock;
+
+    #[test]
+    fn test_args() {
+        let url = url::Url::parse("http://127.0.0.1:4416/").unwrap();
+        assert_eq!(
+            PotProviders::args(&url),
+            vec![
+                "--extractor-args",
+                "youtubepot-bgutilhttp:base_url=http://127.0.0.1:4416"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failover() {
+        let m = mock("GET", "/pot-b/ping").with_status(200).create();
+        let pot = PotProviders::parse(
+            &format!(
+                "http://127.0.0.1:1/pot-a/, {}/pot-b/",
+                mockito::server_url()
+            ),
+            Duration::from_secs(60),
+        )
+        .unwrap();
+        let (a, b) = (pot.providers[0].url.clone(), pot.providers[1].url.clone());
+
+        // Ordinary failures keep the provider
+        assert_eq!(pot.acquire().await.unwrap(), a);
+        pot.report(&a, Some(DownloadFailure::Private));
+        assert_eq!(pot.acquire().await.unwrap(), a);
+
+        pot.report(&a, Some(DownloadFailure::PotFailure));
+        assert_eq!(pot.acquire().await.unwrap(), b);
+
+        // The health check finds out that the first provider is still down
+        pot.check().await;
+        assert_eq!(pot.acquire().await.unwrap(), b);
+        m.assert();
+
+        assert!(PotProviders::parse("", Duration::from_secs(60)).is_err());
+    }
+}
@@ -0,0 +1,278 @@
+use anyhow::Context;
+use serde::Deserialize;
+use std::path::Path;
+
+/// Whether a video can be downloaded right now, as far as the probe can tell.
+/// Removed and private videos fail the probe with a `DownloadFailure` instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Availability {
+    #[default]
+    Public,
+    /// Premiere or live stream that has not started yet
+    Upcoming,
+    /// Live stream that is still running
+    Live,
+    /// Finished live stream whose VOD YouTube is still processing
+    Processing,
+    MembersOnly,
+}
+
+impl Availability {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Availability::Public => "public",
+            Availability::Upcoming => "upcoming",
+            Availability::Live => "live",
+            Availability::Processing => "processing",
+            Availability::MembersOnly => "members_only",
+        }
+    }
+}
+
+/// What is known about a video before downloading it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VideoProbe {
+    pub availability: Availability,
+    /// Duration in seconds
+    pub duration: Option<u64>,
+    /// Estimated download size in bytes of the formats that would be picked
+    pub filesize: Option<u64>,
+    /// When an upcoming video is scheduled to start, in seconds since the epoch
+    pub release_timestamp: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    filesize: Option<u64>,
+    filesize_approx: Option<u64>,
+}
+
+impl ProbeFormat {
+    fn size(&self) -> Option<u64> {
+        self.filesize.or(self.filesize_approx)
+    }
+}
+
+#[derive(Deserialize)]
+struct ProbeJson {
+    live_status: Option<String>,
+    availability: Option<String>,
+    duration: Option<f64>,
+    release_timestamp: Option<i64>,
+    requested_formats: Option<Vec<ProbeFormat>>,
+    #[serde(flatten)]
+    format: ProbeFormat,
+}
+
+impl VideoProbe {
+    /// Parse the output of `yt-dlp --dump-single-json`.
+    pub fn parse(json: &[u8]) -> anyhow::Result<Self> {
+        let json: ProbeJson = serde_json::from_slice(json).context("Could not parse probe")?;
+
+        let availability = match (json.live_status.as_deref(), json.availability.as_deref()) {
+            (Some("is_upcoming"), _) => Availability::Upcoming,
+            (Some("is_live"), _) => Availability::Live,
+            (Some("post_live"), _) => Availability::Processing,
+            (_, Some("subscriber_only")) => Availability::MembersOnly,
+            _ => Availability::Public,
+        };
+
+        // Merged downloads list the video and audio formats separately
+        let filesize = match &json.requested_formats {
+            Some(formats) => formats.iter().map(ProbeFormat::size).sum::<Option<u64>>(),
+            None => json.format.size(),
+        };
+
+        Ok(Self {
+            availability,
+            duration: json.duration.map(|d| d.round() as u64),
+            filesize,
+            release_timestamp: json.release_timestamp,
+        })
+    }
+}
+
+/// Limits a video has to stay within to be downloaded.
+#[derive(Debug, Clone, Default)]
+pub struct ProbePolicy {
+    pub max_filesize: Option<u64>,
+    pub max_duration: Option<u64>,
+    /// Free disk space that has to remain after the download
+    pub min_free_disk: Option<u64>,
+}
+
+/// Why a video was not downloaded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PolicyViolation {
+    /// The video will never fit the policy
+    TooLarge(String),
+    /// The video may fit later, once disk space is freed
+    DiskFull(String),
+}
+
+impl PolicyViolation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PolicyViolation::TooLarge(_) => "too_large",
+            PolicyViolation::DiskFull(_) => "disk_full",
+        }
+    }
+}
+
+impl std::fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PolicyViolation::TooLarge(reason) | PolicyViolation::DiskFull(reason) => {
+                write!(f, "{}", reason)
+            }
+        }
+    }
+}
+
+impl ProbePolicy {
+    /// Parse the policy from its config values. Empty values disable a limit.
+    pub fn parse(
+        max_filesize: &str,
+        max_duration: &str,
+        min_free_disk: &str,
+    ) -> anyhow::Result<Self> {
+        let limit = |value: &str, name: &str| -> anyhow::Result<Option<u64>> {
+            if value.is_empty() {
+                return Ok(None);
+            }
+            Ok(Some(
+                value
+                    .parse()
+                    .with_context(|| format!("Could not parse {}", name))?,
+            ))
+        };
+        Ok(Self {
+            max_filesize: limit(max_filesize, "maximum file size")?,
+            max_duration: limit(max_duration, "maximum duration")?,
+            min_free_disk: limit(min_free_disk, "minimum free disk space")?,
+        })
+    }
+
+    /// Check a probed video against the policy, given the free space on the
+    /// disk it would be downloaded to.
+    pub fn check(&self, probe: &VideoProbe, free_disk: u64) -> Result<(), PolicyViolation> {
+        if let (Some(max), Some(size)) = (self.max_filesize, probe.filesize) {
+            if size > max {
+                return Err(PolicyViolation::TooLarge(format!(
+                    "estimated size {} bytes exceeds maximum {} bytes",
+                    size, max
+                )));
+            }
+        }
+        if let (Some(max), Some(duration)) = (self.max_duration, probe.duration) {
+            if duration > max {
+                return Err(PolicyViolation::TooLarge(format!(
+                    "duration {}s exceeds maximum {}s",
+                    duration, max
+                )));
+            }
+        }
+        if let Some(min) = self.min_free_disk {
+            let needed = min.saturating_add(probe.filesize.unwrap_or(0));
+            if free_disk < needed {
+                return Err(PolicyViolation::DiskFull(format!(
+                    "{} bytes free, {} bytes needed",
+                    free_disk, needed
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn checks_disk(&self) -> bool {
+        self.min_free_disk.is_some()
+    }
+}
+
+/// Free space on the disk holding `path`, in bytes.
+pub fn free_disk_space(path: &Path) -> anyhow::Result<u64> {
+    fs2::available_space(path).context("Could not get free disk space")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let probe = VideoProbe::parse(
+            br#"{"live_status":"not_live","availability":"public","duration":212.4,
+                "requested_formats":[{"filesize":1000},{"filesize_approx":200}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            probe,
+            VideoProbe {
+                availability: Availability::Public,
+                duration: Some(212),
+                filesize: Some(1200),
+                release_timestamp: None,
+            }
+        );
+
+        let probe = VideoProbe::parse(
+            br#"{"live_status":"is_upcoming","release_timestamp":1700000000,"filesize":null}"#,
+        )
+        .unwrap();
+        assert_eq!(probe.availability, Availability::Upcoming);
+        assert_eq!(probe.release_timestamp, Some(1700000000));
+        assert_eq!(probe.filesize, None);
+
+        let probe = VideoProbe::parse(br#"{"live_status":"post_live"}"#).unwrap();
+        assert_eq!(probe.availability, Availability::Processing);
+
+        let probe = VideoProbe::parse(br#"{"availability":"subscriber_only","filesize_approx":5}"#)
+            .unwrap();
+        assert_eq!(probe.availability, Availability::MembersOnly);
+        assert_eq!(probe.filesize, Some(5));
+
+        // Unknown size of one format makes the total unknown
+        let probe = VideoProbe::parse(br#"{"requested_formats":[{"filesize":1000},{}]}"#).unwrap();
+        assert_eq!(probe.filesize, None);
+    }
+
+    #[test]
+    fn test_check() {
+        let policy = ProbePolicy::parse("1000", "600", "500").unwrap();
+        let probe = VideoProbe {
+            duration: Some(300),
+            filesize: Some(800),
+            ..Default::default()
+        };
+        assert_eq!(policy.check(&probe, 2000), Ok(()));
+        assert!(matches!(
+            policy.check(&probe, 1000),
+            Err(PolicyViolation::DiskFull(_))
+        ));
+        assert!(matches!(
+            policy.check(
+                &VideoProbe {
+                    duration: Some(3600),
+                    ..probe.clone()
+                },
+                2000
+            ),
+            Err(PolicyViolation::TooLarge(_))
+        ));
+        assert!(matches!(
+            policy.check(
+                &VideoProbe {
+                    filesize: Some(5000),
+                    ..probe
+                },
+                10000
+            ),
+            Err(PolicyViolation::TooLarge(_))
+        ));
+
+        let policy = ProbePolicy::parse("", "", "").unwrap();
+        assert_eq!(policy.check(&VideoProbe::default(), 0), Ok(()));
+        assert!(!policy.checks_disk());
+        assert!(ProbePolicy::parse("big", "", "").is_err());
+    }
+}
@@ -0,0 +1,272 @@
+use anyhow::Context;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// A named set of yt-dlp download options.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct DownloadProfile {
+    /// Preferred format selector.
+    pub format: String,
+    /// Format selectors tried in order if the preferred one matches nothing.
+    pub fallback_formats: Vec<String>,
+    /// Subtitle languages to fetch. Empty disables subtitles.
+    pub subtitle_langs: String,
+    /// Subtitle format preference.
+    pub subtitle_format: String,
+    /// Fetch comments in a separate stage after the download.
+    pub write_comments: bool,
+    /// Maximum number of comments to fetch.
+    pub max_comments: u64,
+    /// Maximum number of replies to fetch per comment. Zero fetches top-level
+    /// comments only.
+    pub max_comment_replies: u64,
+    /// Time after which fetching comments is given up on.
+    pub comments_timeout_seconds: u64,
+    pub write_thumbnail: bool,
+    /// Container preference for merged video and audio.
+    pub merge_output_format: String,
+    /// Maximum download rate, e.g. `10M`.
+    pub rate_limit: Option<String>,
+    /// Number of fragments of a DASH/HLS video to download concurrently.
+    pub concurrent_fragments: Option<u32>,
+}
+
+impl Default for DownloadProfile {
+    fn default() -> Self {
+        Self {
+            format: "bestvideo+bestaudio".into(),
+            fallback_formats: vec![],
+            subtitle_langs: "all,-live_chat".into(),
+            subtitle_format: "srv3/best".into(),
+            write_comments: true,
+            max_comments: 10000,
+            max_comment_replies: 10,
+            comments_timeout_seconds: 30 * 60,
+            write_thumbnail: true,
+            merge_output_format: "webm/mp4/mkv".into(),
+            rate_limit: None,
+            concurrent_fragments: None,
+        }
+    }
+}
+
+impl DownloadProfile {
+    /// Build the yt-dlp arguments controlled by this profile.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = vec![];
+
+        // Format, with fallbacks chained the way yt-dlp expects
+        let format = std::iter::once(&self.format)
+            .chain(self.fallback_formats.iter())
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("/");
+        args.extend(["-f".into(), format]);
+
+        // Subtitles
+        if !self.subtitle_langs.is_empty() {
+            args.extend([
+                "--write-subs".into(),
+                "--sub-format".into(),
+                self.subtitle_format.clone(),
+                "--sub-langs".into(),
+                self.subtitle_langs.clone(),
+                "--embed-subs".into(),
+            ]);
+        }
+
+        // Metadata
+        if self.write_thumbnail {
+            args.push("--write-thumbnail".into());
+        }
+
+        // Output
+        args.extend([
+            "--merge-output-format".into(),
+            self.merge_output_format.clone(),
+        ]);
+        if let Some(rate_limit) = &self.rate_limit {
+            args.extend(["--limit-rate".into(), rate_limit.clone()]);
+        }
+        if let Some(n) = self.concurrent_fragments {
+            args.extend(["--concurrent-fragments".into(), n.to_string()]);
+        }
+
+        args
+    }
+
+    /// Build the yt-dlp arguments for the comments stage. Incremental fetches
+    /// take the newest comments first.
+    pub fn comments_args(&self, newest_first: bool) -> Vec<String> {
+        let mut extractor_args = format!(
+            "youtube:max_comments={},all,all,{}",
+            self.max_comments, self.max_comment_replies
+        );
+        if newest_first {
+            extractor_args.push_str(";comment_sort=new");
+        }
+        vec![
+            "--write-comments".into(),
+            "--extractor-args".into(),
+            extractor_args,
+        ]
+    }
+}
+
+/// All configured download profiles, and which one to use by default and for
+/// each channel.
+#[derive(Debug)]
+pub struct DownloadProfiles {
+    profiles: HashMap<String, DownloadProfile>,
+    default: String,
+    channels: HashMap<String, String>,
+}
+
+impl Default for DownloadProfiles {
+    fn default() -> Self {
+        Self {
+            profiles: HashMap::from([("default".into(), DownloadProfile::default())]),
+            default: "default".into(),
+            channels: HashMap::new(),
+        }
+    }
+}
+
+impl DownloadProfiles {
+    /// Parse the profiles from their config values. `profiles` is a JSON
+    /// object of profile names to profiles and `channels` a JSON object of
+    /// channel IDs to profile names. Both may be empty, in which case only the
+    /// built-in `default` profile exists.
+    pub fn parse(profiles: &str, default: &str, channels: &str) -> anyhow::Result<Self> {
+        let mut parsed = Self::default();
+        if !profiles.is_empty() {
+            parsed.profiles =
+                serde_json::from_str(profiles).context("Could not parse download profiles")?;
+        }
+        if !channels.is_empty() {
+            parsed.channels = serde_json::from_str(channels)
+                .context("Could not parse channel download profiles")?;
+        }
+        parsed.default = default.into();
+
+        // Catch typos at startup rather than on the first affected task
+        for name in std::iter::once(&parsed.default).chain(parsed.channels.values()) {
+            parsed.get(name)?;
+        }
+        Ok(parsed)
+    }
+
+    pub fn get(&self, name: &str) -> anyhow::Result<&DownloadProfile> {
+        self.profiles
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown download profile {}", name))
+    }
+
+    pub fn has_channel_profiles(&self) -> bool {
+        !self.channels.is_empty()
+    }
+
+    /// Pick the profile for a download: the requested one if any, otherwise
+    /// the one configured for the channel, otherwise the default.
+    pub fn select<'a>(
+        &'a self,
+        requested: Option<&'a str>,
+        channel_id: Option<&str>,
+    ) -> anyhow::Result<(&'a str, &'a DownloadProfile)> {
+        let name = requested
+            .or_else(|| channel_id.and_then(|c| self.channels.get(c).map(|p| p.as_str())))
+            .unwrap_or(&self.default);
+        Ok((name, self.get(name)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_default_args() {
+        assert_eq!(
+            DownloadProfile::default().args(),
+            vec![
+                "-f",
+                "bestvideo+bestaudio",
+                "--write-subs",
+                "--sub-format",
+                "srv3/best",
+                "--sub-langs",
+                "all,-live_chat",
+                "--embed-subs",
+                "--write-thumbnail",
+                "--merge-output-format",
+                "webm/mp4/mkv",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_select() {
+        let profiles = DownloadProfiles::parse(
+            r#"{
+                "default": {},
+                "small": {
+                    "format": "bestvideo[height<=480]+bestaudio",
+                    "fallback_formats": ["best[height<=480]", "best"],
+                    "subtitle_langs": "",
+                    "write_comments": false,
+                    "rate_limit": "5M",
+                    "concurrent_fragments": 4
+                }
+            }"#,
+            "default",
+            r#"{"UCsmall": "small"}"#,
+        )
+        .unwrap();
+
+        let (name, _) = profiles.select(None, Some("UCother")).unwrap();
+        assert_eq!(name, "default");
+        let (name, profile) = profiles.select(None, Some("UCsmall")).unwrap();
+        assert_eq!(name, "small");
+        assert_eq!(
+            profile.args(),
+            vec![
+                "-f",
+                "bestvideo[height<=480]+bestaudio/best[height<=480]/best",
+                "--write-thumbnail",
+                "--merge-output-format",
+                "webm/mp4/mkv",
+                "--limit-rate",
+                "5M",
+                "--concurrent-fragments",
+                "4",
+            ]
+        );
+        let (name, _) = profiles.select(Some("default"), Some("UCsmall")).unwrap();
+        assert_eq!(name, "default");
+        assert!(profiles.select(Some("missing"), None).is_err());
+
+        assert!(DownloadProfiles::parse("", "default", r#"{"UC": "typo"}"#).is_err());
+    }
+
+    #[test]
+    fn test_comments_args() {
+        let profile = DownloadProfile {
+            max_comments: 500,
+            max_comment_replies: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            profile.comments_args(false),
+            vec![
+                "--write-comments",
+                "--extractor-args",
+                "youtube:max_comments=500,all,all,0"
+            ]
+        );
+        assert_eq!(
+            profile.comments_args(true)[2],
+            "youtube:max_comments=500,all,all,0;comment_sort=new"
+        );
+    }
+}
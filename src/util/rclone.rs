@@ -1,3 +1,5 @@
+use super::artifacts::ArtifactSource;
+use super::tools::Tool;
 use super::{SelfInstallable, Uploader};
 use crate::util::{format_path, github};
 use anyhow::Context;
@@ -8,10 +10,12 @@ use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
 
 pub struct Rclone {
+    rclone: Tool,
     rclone_path: PathBuf,
     remote_name: String,
     base_directory: String,
     config_filepath: PathBuf,
+    artifacts: ArtifactSource,
 }
 
 impl Rclone {
@@ -19,6 +23,8 @@ impl Rclone {
         config_data: String,
         remote_name: String,
         base_directory: String,
+        artifacts: ArtifactSource,
+        rclone: Tool,
     ) -> anyhow::Result<Self> {
         debug!(
             "Creating Rclone client with remote {} and base directory {}",
@@ -36,10 +42,12 @@ impl Rclone {
             .context("Could not write rclone config file")?;
 
         let rclone = Rclone {
-            rclone_path: super::get_cache_dir().await?.join("rclone"),
+            rclone_path: rclone.resolve().await?,
+            rclone,
             remote_name,
             base_directory,
             config_filepath,
+            artifacts,
         };
 
         // Check if rclone is installed
@@ -47,6 +55,12 @@ impl Rclone {
             rclone.install().await.context("Could not install rclone")?;
         }
 
+        let version = rclone
+            .rclone
+            .check_version(&rclone.rclone_path, "--version")
+            .await?;
+        info!("Using rclone {}", version);
+
         Ok(rclone)
     }
 }
@@ -64,8 +78,16 @@ impl SelfInstallable for Rclone {
 
     /// Download and install rclone
     async fn install(&self) -> anyhow::Result<()> {
+        if !self.rclone.is_managed() {
+            anyhow::bail!("rclone is not managed by the bot and could not be run");
+        }
+
         info!("Installing rclone");
 
+        if !self.artifacts.is_upstream() {
+            return self.artifacts.install("rclone", &self.rclone_path).await;
+        }
+
         // Create the destination file
         let mut destfile = std::fs::File::create(&self.rclone_path)
             .context("Could not create destination file")?;
@@ -87,7 +109,6 @@ impl SelfInstallable for Rclone {
             .assets
             .into_iter()
             .find(|asset| asset.name.ends_with(asset_name))
-            .take()
             .ok_or_else(|| anyhow::anyhow!("Could not find download URL"))?
             .browser_download_url;
 
@@ -186,11 +207,50 @@ impl Uploader for Rclone {
 mod test {
     use super::*;
 
+    use sha2::{Digest, Sha256};
+
     #[tokio::test]
     async fn test_rclone() {
-        let rclone = Rclone::new("".to_string(), "test".to_string(), "test".to_string())
-            .await
-            .expect("Failed to create Rclone client");
+        // Install a stand-in rclone from an artifact directory
+        let artifacts_dir = tempfile::tempdir().unwrap();
+        let script = b"#!/bin/sh\necho 'rclone v1.66.0'\n";
+        std::fs::write(artifacts_dir.path().join("rclone-linux"), script).unwrap();
+        let sha256 = Sha256::digest(script)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        std::fs::write(
+            artifacts_dir.path().join("manifest.json"),
+            serde_json::json!({
+                "artifacts": [{ "name": "rclone", "file": "rclone-linux", "sha256": sha256 }]
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        // Keep the stand-in out of the shared cache directory
+        let install_dir = tempfile::tempdir().unwrap();
+        let rclone_path = install_dir.path().join("rclone");
+        let rclone = Rclone {
+            rclone: Tool::managed("rclone"),
+            rclone_path: rclone_path.clone(),
+            remote_name: "test".into(),
+            base_directory: "test".into(),
+            config_filepath: install_dir.path().join("rclone.conf"),
+            artifacts: ArtifactSource::Directory(artifacts_dir.path().into()),
+        };
+        rclone.install().await.expect("Failed to install rclone");
+        assert!(rclone.is_installed().await);
+
+        let rclone = Rclone::new(
+            "".to_string(),
+            "test".to_string(),
+            "test".to_string(),
+            ArtifactSource::Upstream,
+            Tool::parse("rclone", &rclone_path.to_string_lossy(), "1.60"),
+        )
+        .await
+        .expect("Failed to create Rclone client");
         assert!(rclone.is_installed().await);
     }
 }
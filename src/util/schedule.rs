@@ -0,0 +1,111 @@
+use super::task::Task;
+use anyhow::Context;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Tasks that must not run before a given time, kept on disk for queue
+/// backends that cannot delay items themselves.
+pub struct Schedule {
+    path: PathBuf,
+    tasks: Mutex<Vec<Task>>,
+}
+
+impl Schedule {
+    /// Load the schedule stored at `path`, starting empty if it does not exist.
+    pub fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let tasks = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).context("Could not parse schedule")?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e).context("Could not read schedule"),
+        };
+        let schedule = Self {
+            path,
+            tasks: Mutex::new(tasks),
+        };
+        schedule.report(&schedule.tasks.lock().unwrap());
+        Ok(schedule)
+    }
+
+    /// Add a task, replacing an earlier entry for the same video and kind.
+    pub fn add(&self, task: Task) -> anyhow::Result<()> {
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.retain(|t| t.id != task.id || t.kind != task.kind);
+        tasks.push(task);
+        self.save(&tasks)
+    }
+
+    /// Remove and return the tasks that are due at `now`, in seconds since the
+    /// epoch, highest priority first.
+    pub fn take_due(&self, now: i64) -> anyhow::Result<Vec<Task>> {
+        let mut tasks = self.tasks.lock().unwrap();
+        let (mut due, pending): (Vec<_>, Vec<_>) = tasks.drain(..).partition(|t| t.is_due(now));
+        *tasks = pending;
+        due.sort_by_key(|t| std::cmp::Reverse(t.priority.unwrap_or(0)));
+        if !due.is_empty() {
+            self.save(&tasks)?;
+        }
+        Ok(due)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write the tasks to a temporary file first, so a crash never leaves a
+    /// truncated schedule behind.
+    fn save(&self, tasks: &[Task]) -> anyhow::Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec(tasks)?).context("Could not write schedule")?;
+        std::fs::rename(&tmp, &self.path).context("Could not replace schedule")?;
+        self.report(tasks);
+        Ok(())
+    }
+
+    fn report(&self, tasks: &[Task]) {
+        super::metrics::gauge_set("archivebot_scheduled_tasks", &[], tasks.len() as f64);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_schedule() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("schedule.json");
+        let schedule = Schedule::load(path.clone()).unwrap();
+        assert!(schedule.is_empty());
+
+        let task = |id: &str, not_before| Task {
+            not_before: Some(not_before),
+            ..Task::new(id)
+        };
+        schedule.add(task("a", 100)).unwrap();
+        schedule.add(task("b", 200)).unwrap();
+        schedule.add(task("a", 300)).unwrap();
+        assert_eq!(schedule.len(), 2);
+
+        // Survives a restart
+        let schedule = Schedule::load(path).unwrap();
+        assert_eq!(schedule.take_due(250).unwrap(), vec![task("b", 200)]);
+        assert!(schedule.take_due(250).unwrap().is_empty());
+        assert_eq!(schedule.take_due(300).unwrap(), vec![task("a", 300)]);
+        assert!(schedule.is_empty());
+
+        schedule.add(task("c", 100)).unwrap();
+        schedule
+            .add(Task {
+                priority: Some(5),
+                ..task("d", 200)
+            })
+            .unwrap();
+        let due = schedule.take_due(300).unwrap();
+        assert_eq!(due[0].id, "d");
+        assert_eq!(due[1].id, "c");
+    }
+}
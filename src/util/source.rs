@@ -0,0 +1,285 @@
+use super::task::Task;
+use super::{DownloadFailure, Metadata};
+
+/// A site videos are archived from. Maps tasks to download URLs and to the
+/// IDs they are archived under.
+pub trait Source: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Prefix of archived IDs, so IDs of different sources cannot collide.
+    /// YouTube IDs are not namespaced, to match the existing archive.
+    fn namespace(&self, _task: &Task) -> Option<String> {
+        Some(self.name().into())
+    }
+
+    /// ID of the video on the site, without any namespace.
+    fn video_id<'a>(&self, task: &'a Task) -> &'a str {
+        task.id
+            .strip_prefix(self.name())
+            .and_then(|id| id.strip_prefix(':'))
+            .unwrap_or(&task.id)
+    }
+
+    fn url(&self, task: &Task) -> anyhow::Result<String>;
+
+    /// URL of the channel or playlist a playlist task lists.
+    fn playlist_url(&self, task: &Task) -> anyhow::Result<String> {
+        task.url
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("Playlist task {} has no URL", task.id))
+    }
+
+    /// ID the video is archived under.
+    fn archive_id(&self, task: &Task) -> String {
+        let id = self.video_id(task);
+        match self.namespace(task) {
+            Some(namespace) => format!("{}:{}", namespace, id),
+            None => id.into(),
+        }
+    }
+
+    /// Namespace the IDs in metadata extracted from yt-dlp's info.json.
+    fn map_metadata(&self, task: &Task, metadata: &mut Metadata) {
+        metadata.video_id = self.archive_id(task);
+        if let Some(namespace) = self.namespace(task) {
+            if !metadata.channel_id.is_empty() {
+                metadata.channel_id = format!("{}:{}", namespace, metadata.channel_id);
+            }
+        }
+    }
+}
+
+pub struct YouTube;
+
+impl Source for YouTube {
+    fn name(&self) -> &'static str {
+        "youtube"
+    }
+
+    fn namespace(&self, _task: &Task) -> Option<String> {
+        None
+    }
+
+    fn url(&self, task: &Task) -> anyhow::Result<String> {
+        Ok(task
+            .url
+            .clone()
+            .unwrap_or_else(|| format!("https://www.youtube.com/watch?v={}", self.video_id(task))))
+    }
+
+    /// Channel IDs list the channel's uploads, anything else is a playlist ID.
+    fn playlist_url(&self, task: &Task) -> anyhow::Result<String> {
+        let id = self.video_id(task);
+        Ok(task
+            .url
+            .clone()
+            .unwrap_or_else(|| match id.starts_with("UC") {
+                true => format!("https://www.youtube.com/channel/{}", id),
+                false => format!("https://www.youtube.com/playlist?list={}", id),
+            }))
+    }
+}
+
+/// Twitch VODs, identified by their numeric ID with or without the `v` prefix.
+pub struct Twitch;
+
+impl Twitch {
+    fn vod_id<'a>(&self, task: &'a Task) -> &'a str {
+        let id = self.video_id(task);
+        id.strip_prefix('v').unwrap_or(id)
+    }
+}
+
+impl Source for Twitch {
+    fn name(&self) -> &'static str {
+        "twitch"
+    }
+
+    fn url(&self, task: &Task) -> anyhow::Result<String> {
+        Ok(task
+            .url
+            .clone()
+            .unwrap_or_else(|| format!("https://www.twitch.tv/videos/{}", self.vod_id(task))))
+    }
+
+    fn archive_id(&self, task: &Task) -> String {
+        format!("twitch:{}", self.vod_id(task))
+    }
+}
+
+/// Any other site yt-dlp supports. Tasks need a URL, whose host namespaces
+/// the archived ID. Archived IDs, `host:id`, resolve back to this source.
+pub struct Generic;
+
+impl Source for Generic {
+    fn name(&self) -> &'static str {
+        "generic"
+    }
+
+    fn namespace(&self, task: &Task) -> Option<String> {
+        let host = task
+            .url
+            .as_deref()
+            .and_then(|url| url::Url::parse(url).ok())
+            .and_then(|url| {
+                url.host_str()
+                    .map(|h| h.trim_start_matches("www.").to_string())
+            });
+        let prefix = task
+            .id
+            .split_once(':')
+            .map(|(prefix, _)| prefix.to_string());
+        Some(host.or(prefix).unwrap_or_else(|| self.name().into()))
+    }
+
+    fn video_id<'a>(&self, task: &'a Task) -> &'a str {
+        task.id.split_once(':').map_or(&task.id, |(_, id)| id)
+    }
+
+    fn url(&self, task: &Task) -> anyhow::Result<String> {
+        task.url.clone().ok_or_else(|| {
+            anyhow::Error::new(DownloadFailure::Rejected)
+                .context(format!("Task {} has no URL to download", task.id))
+        })
+    }
+}
+
+fn platform(name: &str) -> Option<&'static dyn Source> {
+    match name {
+        "youtube" => Some(&YouTube),
+        "twitch" => Some(&Twitch),
+        "generic" => Some(&Generic),
+        _ => None,
+    }
+}
+
+/// Pick the source of a task from its platform, the namespace of its ID or
+/// the host of its URL. Tasks with none of those are YouTube videos.
+pub fn resolve(task: &Task) -> anyhow::Result<&'static dyn Source> {
+    if let Some(name) = task.platform.as_deref() {
+        return platform(name).ok_or_else(|| anyhow::anyhow!("Unknown platform {}", name));
+    }
+    if let Some((namespace, _)) = task.id.split_once(':') {
+        // Generic sources are namespaced by host
+        return match platform(namespace) {
+            Some(source) => Ok(source),
+            None if namespace.contains('.') => Ok(&Generic),
+            None => Err(anyhow::anyhow!("Unknown platform {}", namespace)),
+        };
+    }
+
+    let host = task
+        .url
+        .as_deref()
+        .and_then(|url| url::Url::parse(url).ok())
+        .and_then(|url| url.host_str().map(String::from));
+    Ok(match host.as_deref() {
+        None => &YouTube,
+        Some(host)
+            if ["youtube.com", "youtu.be"]
+                .iter()
+                .any(|d| is_domain(host, d)) =>
+        {
+            &YouTube
+        }
+        Some(host) if is_domain(host, "twitch.tv") => &Twitch,
+        Some(_) => &Generic,
+    })
+}
+
+fn is_domain(host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn task(data: &str) -> Task {
+        data.parse().unwrap()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let youtube = task("dQw4w9WgXcQ");
+        let source = resolve(&youtube).unwrap();
+        assert_eq!(source.name(), "youtube");
+        assert_eq!(
+            source.url(&youtube).unwrap(),
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ"
+        );
+        assert_eq!(source.archive_id(&youtube), "dQw4w9WgXcQ");
+        assert_eq!(
+            source
+                .playlist_url(&task(
+                    r#"{"id":"UCuAXFkgsw1L7xaCfnd5JJOw","kind":"playlist"}"#
+                ))
+                .unwrap(),
+            "https://www.youtube.com/channel/UCuAXFkgsw1L7xaCfnd5JJOw"
+        );
+
+        for data in [
+            "twitch:v123456",
+            r#"{"id":"123456","platform":"twitch"}"#,
+            r#"{"id":"v123456","url":"https://www.twitch.tv/videos/123456"}"#,
+        ] {
+            let twitch = task(data);
+            let source = resolve(&twitch).unwrap();
+            assert_eq!(source.name(), "twitch");
+            assert_eq!(
+                source.url(&twitch).unwrap(),
+                "https://www.twitch.tv/videos/123456"
+            );
+            assert_eq!(source.archive_id(&twitch), "twitch:123456");
+        }
+
+        let generic = task(r#"{"id":"76979871","url":"https://vimeo.com/76979871"}"#);
+        let source = resolve(&generic).unwrap();
+        assert_eq!(source.name(), "generic");
+        assert_eq!(source.archive_id(&generic), "vimeo.com:76979871");
+        assert!(source
+            .url(&task(r#"{"id":"1","platform":"generic"}"#))
+            .is_err());
+
+        assert!(resolve(&task("dailymotion:x7tgad0")).is_err());
+    }
+
+    #[test]
+    fn test_archive_id_round_trip() {
+        for data in [
+            "dQw4w9WgXcQ",
+            "twitch:v123456",
+            r#"{"id":"76979871","url":"https://vimeo.com/76979871"}"#,
+            r#"{"id":"videos-1","url":"https://example.com/videos/1","platform":"generic"}"#,
+        ] {
+            let original = task(data);
+            let archive_id = resolve(&original).unwrap().archive_id(&original);
+            let archived = task(&format!(r#"{{"id":"{}","kind":"refresh"}}"#, archive_id));
+            let source = resolve(&archived).unwrap();
+            assert_eq!(source.name(), resolve(&original).unwrap().name());
+            assert_eq!(source.archive_id(&archived), archive_id);
+        }
+    }
+
+    #[test]
+    fn test_map_metadata() {
+        let twitch = task("twitch:123456");
+        let mut metadata = Metadata {
+            video_id: "v123456".into(),
+            channel_id: "streamer".into(),
+            ..Default::default()
+        };
+        Twitch.map_metadata(&twitch, &mut metadata);
+        assert_eq!(metadata.video_id, "twitch:123456");
+        assert_eq!(metadata.channel_id, "twitch:streamer");
+
+        let mut metadata = Metadata {
+            video_id: "dQw4w9WgXcQ".into(),
+            channel_id: "UCuAXFkgsw1L7xaCfnd5JJOw".into(),
+            ..Default::default()
+        };
+        YouTube.map_metadata(&task("dQw4w9WgXcQ"), &mut metadata);
+        assert_eq!(metadata.video_id, "dQw4w9WgXcQ");
+        assert_eq!(metadata.channel_id, "UCuAXFkgsw1L7xaCfnd5JJOw");
+    }
+}
@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+
+/// What a task asks the bot to do.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskKind {
+    /// Archive a video
+    #[default]
+    Video,
+    /// Add the live chat to an already archived video
+    LiveChat,
+    /// Fetch comments of an already archived video posted since the last fetch
+    Comments,
+    /// Queue the videos of a channel, playlist or channel tab
+    Playlist,
+    /// Update the metadata of an already archived video
+    Refresh,
+}
+
+impl TaskKind {
+    fn is_default(&self) -> bool {
+        *self == TaskKind::default()
+    }
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+/// A unit of work taken from the task queue. Tasks are stored either as a bare
+/// video ID or as a JSON object carrying extra options.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Task {
+    /// Video ID
+    pub id: String,
+    #[serde(default, skip_serializing_if = "TaskKind::is_default")]
+    pub kind: TaskKind,
+    /// URL to download instead of the one the source builds from the ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Source of the video, e.g. `twitch`. Guessed from the ID or URL if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+    /// Download profile to use instead of the channel or default profile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Cookie jar to use instead of the channel's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cookies: Option<String>,
+    /// Do not run the task before this time, in seconds since the epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<i64>,
+    /// Directory to upload to instead of the ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_path: Option<String>,
+    /// Higher priorities are released from the local schedule first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    /// Who asked for the video to be archived, recorded in its metadata
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requester: Option<String>,
+    /// Number of failed attempts so far
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub attempt: u32,
+    /// Archive the video again even if it is already archived
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub force: bool,
+    /// Which entries of a playlist task to queue
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<super::playlist::PlaylistFilter>,
+}
+
+impl Task {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Whether the task may run at `now`, in seconds since the epoch.
+    pub fn is_due(&self, now: i64) -> bool {
+        self.not_before.is_none_or(|t| t <= now)
+    }
+}
+
+impl std::str::FromStr for Task {
+    type Err = anyhow::Error;
+
+    fn from_str(data: &str) -> anyhow::Result<Self> {
+        let data = data.trim();
+        if data.starts_with('{') {
+            Ok(serde_json::from_str(data)?)
+        } else {
+            Ok(Task::new(data))
+        }
+    }
+}
+
+impl std::fmt::Display for Task {
+    /// Format the task the way it is stored in the queue. Tasks without
+    /// options stay bare IDs so they remain readable by older versions.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if *self == Task::new(&self.id) {
+            write!(f, "{}", self.id)
+        } else {
+            let json = serde_json::to_string(self).map_err(|_| std::fmt::Error)?;
+            write!(f, "{}", json)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let task: Task = "dQw4w9WgXcQ".parse().unwrap();
+        assert_eq!(task, Task::new("dQw4w9WgXcQ"));
+        assert_eq!(task.to_string(), "dQw4w9WgXcQ");
+
+        let task: Task = r#"{"id":"dQw4w9WgXcQ","profile":"small"}"#.parse().unwrap();
+        assert_eq!(task.id, "dQw4w9WgXcQ");
+        assert_eq!(task.profile.as_deref(), Some("small"));
+        assert_eq!(
+            task.to_string(),
+            r#"{"id":"dQw4w9WgXcQ","profile":"small"}"#
+        );
+
+        let task: Task = r#"{"id":"dQw4w9WgXcQ","kind":"live_chat"}"#.parse().unwrap();
+        assert_eq!(task.kind, TaskKind::LiveChat);
+        assert_eq!(
+            task.to_string(),
+            r#"{"id":"dQw4w9WgXcQ","kind":"live_chat"}"#
+        );
+
+        let task: Task = r#"{"id":"dQw4w9WgXcQ","not_before":1700000000}"#.parse().unwrap();
+        assert!(!task.is_due(1699999999));
+        assert!(task.is_due(1700000000));
+        assert!(Task::new("dQw4w9WgXcQ").is_due(0));
+
+        let task: Task = r#"{"id":"abc","url":"https://example.com/v/abc","storage_path":"x/abc","attempt":2,"force":true}"#
+            .parse()
+            .unwrap();
+        assert_eq!(task.url.as_deref(), Some("https://example.com/v/abc"));
+        assert_eq!(task.storage_path.as_deref(), Some("x/abc"));
+        assert_eq!(task.attempt, 2);
+        assert!(task.force);
+
+        assert!("{not json".parse::<Task>().is_err());
+    }
+}
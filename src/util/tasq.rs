@@ -20,7 +20,7 @@ impl Tasq {
     /// Create a new Tasq client. The URL should already include the list ID.
     pub async fn new(url: String, client: Option<Client>) -> anyhow::Result<Self> {
         debug!("Creating Tasq client with URL {}", url);
-        let client = client.unwrap_or_else(|| Client::new());
+        let client = client.unwrap_or_default();
         Ok(Tasq { url, client })
     }
 }
@@ -0,0 +1,206 @@
+use anyhow::Context;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// How the binary of a tool is found.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ToolLocation {
+    /// Installed into and updated in the cache directory by the bot.
+    Managed,
+    /// Looked up on `PATH`.
+    Path,
+    /// Used from the given path as is.
+    Explicit(PathBuf),
+}
+
+/// An external tool such as yt-dlp, ffmpeg or rclone.
+#[derive(Clone, Debug)]
+pub struct Tool {
+    pub name: String,
+    pub location: ToolLocation,
+    /// Oldest acceptable version, e.g. `2025.01.01` or `1.65`.
+    pub min_version: Option<String>,
+}
+
+impl Tool {
+    /// Create a tool from its config values. The location is `managed`,
+    /// `path` or an explicit path to the binary, and an empty minimum version
+    /// accepts any version.
+    pub fn parse(name: &str, location: &str, min_version: &str) -> Self {
+        let location = match location {
+            "" | "managed" => ToolLocation::Managed,
+            "path" => ToolLocation::Path,
+            path => ToolLocation::Explicit(PathBuf::from(path)),
+        };
+        Self {
+            name: name.into(),
+            location,
+            min_version: if min_version.is_empty() {
+                None
+            } else {
+                Some(min_version.into())
+            },
+        }
+    }
+
+    pub fn managed(name: &str) -> Self {
+        Self::parse(name, "managed", "")
+    }
+
+    pub fn is_managed(&self) -> bool {
+        self.location == ToolLocation::Managed
+    }
+
+    /// Get the path of the binary. For managed tools this is the location in
+    /// the cache directory, which may not exist yet.
+    pub async fn resolve(&self) -> anyhow::Result<PathBuf> {
+        match &self.location {
+            ToolLocation::Managed => Ok(super::get_cache_dir().await?.join(&self.name)),
+            ToolLocation::Explicit(path) => Ok(path.clone()),
+            ToolLocation::Path => {
+                let path = std::env::var_os("PATH").unwrap_or_default();
+                std::env::split_paths(&path)
+                    .map(|dir| dir.join(&self.name))
+                    .find(|candidate| {
+                        candidate
+                            .metadata()
+                            .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+                            .unwrap_or(false)
+                    })
+                    .ok_or_else(|| anyhow::anyhow!("Could not find {} on PATH", self.name))
+            }
+        }
+    }
+
+    /// Run the binary at `path` to get its version, and make sure it is not
+    /// older than the minimum version.
+    pub async fn check_version(&self, path: &Path, version_arg: &str) -> anyhow::Result<String> {
+        let output = Command::new(path)
+            .arg(version_arg)
+            .output()
+            .await
+            .with_context(|| format!("Could not run {}", path.display()))?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "{} {} exited with status {}",
+                self.name,
+                version_arg,
+                output.status
+            );
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let version = parse_version(&stdout)
+            .ok_or_else(|| anyhow::anyhow!("Could not detect {} version", self.name))?;
+        debug!("Found {} {} at {}", self.name, version, path.display());
+
+        if let Some(min_version) = &self.min_version {
+            if version_cmp(&version, min_version) == std::cmp::Ordering::Less {
+                anyhow::bail!(
+                    "{} {} is older than the minimum version {}",
+                    self.name,
+                    version,
+                    min_version
+                );
+            }
+        }
+        Ok(version)
+    }
+}
+
+/// Find the version in a tool's output: the token after the word `version`,
+/// e.g. `5.0.1` in `ffmpeg version 5.0.1-static`, or else the first
+/// version-like token, e.g. `2025.10.22` in yt-dlp's output or `1.68.1` in
+/// `rclone v1.68.1`. Git builds of ffmpeg (`ffmpeg version N-112345-g…`) give
+/// their revision number, which is newer than any release.
+fn parse_version(output: &str) -> Option<String> {
+    let numeric = |token: &str| {
+        token
+            .trim_start_matches(|c: char| !c.is_ascii_digit())
+            .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .next()
+            .map(|v| v.trim_end_matches('.').to_string())
+            .filter(|v| !v.is_empty())
+    };
+
+    let mut tokens = output.split_whitespace();
+    if tokens.any(|token| token == "version") {
+        return tokens.next().and_then(numeric);
+    }
+    output
+        .split_whitespace()
+        .find_map(|token| numeric(token).filter(|v| v.contains('.')))
+}
+
+/// Compare two dotted version strings numerically.
+fn version_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    let parts = |v: &str| {
+        v.split('.')
+            .map(|p| p.parse::<u64>().unwrap_or(0))
+            .collect::<Vec<_>>()
+    };
+    let (a, b) = (parts(a), parts(b));
+    let len = a.len().max(b.len());
+    (0..len)
+        .map(|i| a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)))
+        .find(|o| o.is_ne())
+        .unwrap_or(std::cmp::Ordering::Equal)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cmp::Ordering;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("2025.10.22\n").unwrap(), "2025.10.22");
+        assert_eq!(
+            parse_version("rclone v1.68.1\n- os/version: debian 12").unwrap(),
+            "1.68.1"
+        );
+        assert_eq!(
+            parse_version("ffmpeg version 5.0.1-static https://johnvansickle.com").unwrap(),
+            "5.0.1"
+        );
+        assert_eq!(
+            parse_version("ffmpeg version n6.1 Copyright").unwrap(),
+            "6.1"
+        );
+        // Not the gcc version further down
+        assert_eq!(
+            parse_version(
+                "ffmpeg version N-112345-g1a2b3c4d5e-20240101 Copyright (c) 2000-2024 the FFmpeg developers\nbuilt with gcc 13.2.1 (GCC) 20231014"
+            )
+            .unwrap(),
+            "112345"
+        );
+        assert!(parse_version("no version here").is_none());
+    }
+
+    #[test]
+    fn test_version_cmp() {
+        assert_eq!(version_cmp("2025.10.22", "2025.9.30"), Ordering::Greater);
+        assert_eq!(version_cmp("1.68", "1.68.0"), Ordering::Equal);
+        assert_eq!(version_cmp("5.0.1", "6"), Ordering::Less);
+    }
+
+    #[tokio::test]
+    async fn test_check_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fake-tool");
+        std::fs::write(&path, "#!/bin/sh\necho 'fake-tool v1.2.3'\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let tool = Tool::parse("fake-tool", path.to_str().unwrap(), "1.2");
+        assert_eq!(tool.resolve().await.unwrap(), path);
+        assert_eq!(
+            tool.check_version(&path, "--version").await.unwrap(),
+            "1.2.3"
+        );
+
+        let tool = Tool::parse("fake-tool", path.to_str().unwrap(), "1.10");
+        assert!(tool.check_version(&path, "--version").await.is_err());
+    }
+}
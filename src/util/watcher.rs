@@ -0,0 +1,316 @@
+use super::task::Task;
+use super::TaskQueue;
+use anyhow::Context;
+use reqwest::Client;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How many IDs to remember per feed. Feeds only list the latest uploads, so
+/// older IDs never show up again.
+const SEEN_PER_FEED: usize = 500;
+
+/// One upload listed in a feed.
+#[derive(Debug, PartialEq)]
+pub struct FeedEntry {
+    pub id: String,
+    /// Link to the upload, used for feeds that are not YouTube's
+    pub url: Option<String>,
+}
+
+impl FeedEntry {
+    fn task(&self) -> Task {
+        Task {
+            url: self.url.clone(),
+            platform: self.url.as_ref().map(|_| "generic".into()),
+            ..Task::new(&self.id)
+        }
+    }
+}
+
+/// ID of a link in a feed other than YouTube's, built from its path. Atom IDs
+/// and RSS GUIDs are usually URIs, which cannot be told apart from a
+/// namespaced task ID.
+fn link_id(link: &str) -> Option<String> {
+    let url = url::Url::parse(link).ok()?;
+    let id = url.path().trim_matches('/').replace('/', "-");
+    (!id.is_empty()).then_some(id)
+}
+
+/// Parse an Atom or RSS feed. YouTube entries are identified by their video
+/// ID, other entries by the path of their link.
+pub fn parse_feed(xml: &str) -> anyhow::Result<Vec<FeedEntry>> {
+    let doc = roxmltree::Document::parse(xml).context("Could not parse feed")?;
+    let child_text = |node: roxmltree::Node, name: &str| {
+        node.children()
+            .find(|c| c.tag_name().name() == name)
+            .and_then(|c| c.text())
+            .map(|t| t.trim().to_string())
+    };
+
+    Ok(doc
+        .descendants()
+        .filter(|n| matches!(n.tag_name().name(), "entry" | "item"))
+        .filter_map(|entry| {
+            if let Some(video_id) = child_text(entry, "videoId") {
+                return Some(FeedEntry {
+                    id: video_id,
+                    url: None,
+                });
+            }
+            let url = entry
+                .children()
+                .find(|c| c.tag_name().name() == "link")
+                .and_then(|link| link.attribute("href").or_else(|| link.text()))
+                .map(|l| l.trim().to_string())?;
+            Some(FeedEntry {
+                id: link_id(&url)?,
+                url: Some(url),
+            })
+        })
+        .collect())
+}
+
+/// Polls the feeds of channels and queues their new uploads.
+pub struct Watcher {
+    feeds: Vec<String>,
+    interval: Duration,
+    task_queue: Box<dyn TaskQueue>,
+    client: Client,
+    seen_path: PathBuf,
+    seen: Mutex<HashMap<String, Vec<String>>>,
+}
+
+impl Watcher {
+    /// Create a watcher for a comma separated list of YouTube channel IDs and
+    /// feed URLs. IDs already queued are remembered in `seen_path`.
+    pub fn new(
+        channels: &str,
+        interval: Duration,
+        task_queue: Box<dyn TaskQueue>,
+        seen_path: PathBuf,
+        client: Option<Client>,
+    ) -> anyhow::Result<Self> {
+        let feeds = channels
+            .split(',')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .map(|c| match c.starts_with("http") {
+                true => c.to_string(),
+                false => format!("https://www.youtube.com/feeds/videos.xml?channel_id={}", c),
+            })
+            .collect();
+        let seen = match std::fs::read(&seen_path) {
+            Ok(data) => serde_json::from_slice(&data).context("Could not parse seen videos")?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e).context("Could not read seen videos"),
+        };
+        Ok(Self {
+            feeds,
+            interval,
+            task_queue,
+            client: client.unwrap_or_default(),
+            seen_path,
+            seen: Mutex::new(seen),
+        })
+    }
+
+    pub async fn run_forever(&self) {
+        loop {
+            for feed in &self.feeds {
+                if let Err(e) = self.poll(feed).await {
+                    warn!("Could not poll feed {}: {:#}", feed, e);
+                    super::metrics::counter_inc(
+                        "archivebot_watcher_poll_failures_total",
+                        &[("feed", feed)],
+                    );
+                }
+            }
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+
+    /// Fetch a feed and queue the entries not seen before. Returns how many
+    /// were queued.
+    pub async fn poll(&self, feed: &str) -> anyhow::Result<usize> {
+        debug!("Polling feed {}", feed);
+        let xml = self
+            .client
+            .get(feed)
+            .send()
+            .await
+            .context("Could not send request")?
+            .error_for_status()
+            .context("Unexpected status code")?
+            .text()
+            .await
+            .context("Could not read feed")?;
+        let entries = parse_feed(&xml)?;
+
+        let new: Vec<_> = {
+            let seen = self.seen.lock().unwrap();
+            let seen = seen.get(feed);
+            entries
+                .iter()
+                .filter(|e| seen.is_none_or(|s| !s.contains(&e.id)))
+                .collect()
+        };
+        for entry in &new {
+            info!("Queueing new upload {} from {}", entry.id, feed);
+            self.task_queue
+                .insert(entry.task().to_string())
+                .await
+                .context("Could not queue new upload")?;
+            // Remember each entry as soon as it is queued, so a failure does
+            // not queue the earlier ones again
+            self.remember(feed, &entry.id)?;
+        }
+        super::metrics::counter_add("archivebot_watcher_new_videos_total", &[], new.len() as f64);
+        Ok(new.len())
+    }
+
+    fn remember(&self, feed: &str, id: &str) -> anyhow::Result<()> {
+        let mut seen = self.seen.lock().unwrap();
+        let ids = seen.entry(feed.to_string()).or_default();
+        ids.push(id.to_string());
+        if ids.len() > SEEN_PER_FEED {
+            ids.drain(..ids.len() - SEEN_PER_FEED);
+        }
+
+        let tmp = self.seen_path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&*seen)?).context("Could not write seen videos")?;
+        std::fs::rename(&tmp, &self.seen_path).context("Could not replace seen videos")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{TaskConsumeResponse, TaskInsertResponse, TaskListResponse};
+    use super::*;
+    use async_trait::async_trait;
+    use mockito::mock;
+    use std::sync::Arc;
+
+    static YOUTUBE_FEED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns:yt="http://www.youtube.com/xml/schemas/2015" xmlns="http://www.w3.org/2005/Atom">
+ <title>Rick Astley</title>
+ <entry>
+  <id>yt:video:dQw4w9WgXcQ</id>
+  <yt:videoId>dQw4w9WgXcQ</yt:videoId>
+  <link rel="alternate" href="https://www.youtube.com/watch?v=dQw4w9WgXcQ"/>
+ </entry>
+ <entry>
+  <id>yt:video:yPYZpwSpKmA</id>
+  <yt:videoId>yPYZpwSpKmA</yt:videoId>
+ </entry>
+</feed>"#;
+
+    #[derive(Clone, Default)]
+    struct MockQueue(Arc<Mutex<Vec<String>>>);
+
+    #[async_trait]
+    impl TaskQueue for MockQueue {
+        async fn insert(&self, data: String) -> anyhow::Result<TaskInsertResponse> {
+            self.0.lock().unwrap().push(data.clone());
+            Ok(TaskInsertResponse { key: data })
+        }
+
+        async fn list(&self) -> anyhow::Result<TaskListResponse> {
+            unimplemented!()
+        }
+
+        async fn consume(&self) -> anyhow::Result<TaskConsumeResponse> {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn test_parse_feed() {
+        let entries = parse_feed(YOUTUBE_FEED).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].id, "dQw4w9WgXcQ");
+        assert_eq!(entries[0].url, None);
+
+        let rss = r#"<rss version="2.0"><channel><item>
+            <guid>1</guid><link>https://example.com/videos/1</link>
+        </item></channel></rss>"#;
+        assert_eq!(
+            parse_feed(rss).unwrap(),
+            vec![FeedEntry {
+                id: "videos-1".into(),
+                url: Some("https://example.com/videos/1".into()),
+            }]
+        );
+        assert!(parse_feed("<feed>").is_err());
+    }
+
+    #[test]
+    fn test_feed_task() {
+        let atom = r#"<feed xmlns="http://www.w3.org/2005/Atom"><entry>
+            <id>tag:vimeo.com,2024:clip76979871</id>
+            <link rel="alternate" href="https://vimeo.com/76979871"/>
+        </entry></feed>"#;
+        let task: Task = parse_feed(atom).unwrap()[0]
+            .task()
+            .to_string()
+            .parse()
+            .unwrap();
+        let source = super::super::source::resolve(&task).unwrap();
+        assert_eq!(source.name(), "generic");
+        assert_eq!(source.archive_id(&task), "vimeo.com:76979871");
+        assert_eq!(source.url(&task).unwrap(), "https://vimeo.com/76979871");
+
+        let youtube: Task = parse_feed(YOUTUBE_FEED).unwrap()[0]
+            .task()
+            .to_string()
+            .parse()
+            .unwrap();
+        assert_eq!(
+            super::super::source::resolve(&youtube).unwrap().name(),
+            "youtube"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_poll() {
+        let _m = mock("GET", "/feeds/videos.xml")
+            .with_status(200)
+            .with_header("content-type", "application/atom+xml")
+            .with_body(YOUTUBE_FEED)
+            .create();
+        let feed = format!("{}/feeds/videos.xml", mockito::server_url());
+        let dir = tempfile::tempdir().unwrap();
+        let seen_path = dir.path().join("seen.json");
+        let queue = MockQueue::default();
+
+        let watcher = Watcher::new(
+            &format!("{}, ", feed),
+            Duration::from_secs(60),
+            Box::new(queue.clone()),
+            seen_path.clone(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(watcher.feeds, vec![feed.clone()]);
+        assert_eq!(watcher.poll(&feed).await.unwrap(), 2);
+        assert_eq!(watcher.poll(&feed).await.unwrap(), 0);
+        assert_eq!(*queue.0.lock().unwrap(), ["dQw4w9WgXcQ", "yPYZpwSpKmA"]);
+
+        // Seen videos survive a restart
+        let watcher = Watcher::new(
+            "UCuAXFkgsw1L7xaCfnd5JJOw",
+            Duration::from_secs(60),
+            Box::new(queue.clone()),
+            seen_path,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            watcher.feeds,
+            vec!["https://www.youtube.com/feeds/videos.xml?channel_id=UCuAXFkgsw1L7xaCfnd5JJOw"]
+        );
+        assert_eq!(watcher.poll(&feed).await.unwrap(), 0);
+    }
+}